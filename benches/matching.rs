@@ -0,0 +1,129 @@
+//! Matching benchmark over seeded synthetic order flow, run with `cargo bench`.
+//!
+//! Options go after `cargo bench --bench matching --`, each followed by its value: `--seed`,
+//! `--commands`, `--cancel-ratio`, `--market-ratio`, `--mean` and `--deviation` (normal prices,
+//! in ticks) or `--low` and `--high` (uniform prices, in ticks), and `--container` with `heap` or
+//! `tree` for the limit order container.
+extern crate matching;
+extern crate serde_json;
+
+use std::env;
+use std::hint::black_box;
+use std::process;
+use std::time::{Duration, Instant};
+use matching::structs::*;
+use matching::ds::{Queue, Heap, List, Tree};
+use matching::synthetic::{self, FlowConfig, PriceDistribution};
+
+#[derive(Debug, Clone, Copy)]
+enum Container {
+	Heap,
+	Tree
+}
+
+fn parse_config() -> (FlowConfig, Container) {
+	let mut config = FlowConfig::default();
+	let mut container = Container::Heap;
+	let (mut mean, mut deviation, mut low, mut high) = (None, None, None, None);
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		// cargo bench passes --bench to every bench target
+		if arg == "--bench" {
+			continue
+		}
+		let value = args.next().unwrap_or_else(|| usage(&arg));
+		match arg.as_str() {
+			"--seed" => config.seed = value.parse().unwrap_or_else(|_| usage(&arg)),
+			"--commands" => config.commands = value.parse().unwrap_or_else(|_| usage(&arg)),
+			"--cancel-ratio" => config.cancel_ratio = value.parse().unwrap_or_else(|_| usage(&arg)),
+			"--market-ratio" => config.market_ratio = value.parse().unwrap_or_else(|_| usage(&arg)),
+			"--mean" => mean = Some(value.parse().unwrap_or_else(|_| usage(&arg))),
+			"--deviation" => deviation = Some(value.parse().unwrap_or_else(|_| usage(&arg))),
+			"--low" => low = Some(value.parse().unwrap_or_else(|_| usage(&arg))),
+			"--high" => high = Some(value.parse().unwrap_or_else(|_| usage(&arg))),
+			"--container" => container = match value.as_str() {
+				"heap" => Container::Heap,
+				"tree" => Container::Tree,
+				_ => usage(&arg)
+			},
+			_ => usage(&arg)
+		}
+	}
+	if low.is_some() || high.is_some() {
+		config.prices = PriceDistribution::Uniform { low: low.unwrap_or(9_900), high: high.unwrap_or(10_100) };
+	} else if let PriceDistribution::Normal { mean: default_mean, deviation: default_deviation } = config.prices {
+		config.prices = PriceDistribution::Normal {
+			mean: mean.unwrap_or(default_mean),
+			deviation: deviation.unwrap_or(default_deviation)
+		};
+	}
+	(config, container)
+}
+
+fn usage(arg: &str) -> ! {
+	eprintln!("invalid option {}", arg);
+	process::exit(2)
+}
+
+fn percentile(sorted: &[Duration], fraction: f64) -> Duration {
+	sorted[((sorted.len() - 1) as f64 * fraction).round() as usize]
+}
+
+fn per_second(count: usize, elapsed: Duration) -> f64 {
+	count as f64 / elapsed.as_secs_f64()
+}
+
+fn main() {
+	let (config, container) = parse_config();
+	let commands = synthetic::generate(&config);
+	println!("{:?}", config);
+	println!("container         {:?}", container);
+
+	let lines: Vec<String> = commands.iter().map(|command| serde_json::to_string(command).unwrap()).collect();
+	let start = Instant::now();
+	for line in lines.iter() {
+		black_box(serde_json::from_str::<Command>(line).unwrap());
+	}
+	let parsing = start.elapsed();
+	println!("parsing           {:.0} commands/s", per_second(lines.len(), parsing));
+
+	match container {
+		Container::Heap => run::<Heap<Order>, List<Order>>(commands),
+		Container::Tree => run::<Tree<Order>, List<Order>>(commands)
+	}
+}
+
+fn run<L: Queue<Order>, M: Queue<Order>>(commands: Vec<Command>) {
+	let mut market: Market<L, M> = Market::with_containers(1);
+	let inputs = commands.clone();
+	let start = Instant::now();
+	let mut trades = 0;
+	for command in inputs {
+		if let Ok(result) = market.execute(command) {
+			trades += result.trades.len();
+		}
+	}
+	let matching = start.elapsed();
+
+	let mut market: Market<L, M> = Market::with_containers(1);
+	let mut latencies = Vec::with_capacity(commands.len());
+	for command in commands {
+		let start = Instant::now();
+		black_box(market.execute(command).ok());
+		latencies.push(start.elapsed());
+	}
+	latencies.sort();
+
+	println!("commands          {}", latencies.len());
+	println!("trades            {}", trades);
+	println!("resting orders    {} asks, {} bids", market.ask_book.limit_orders.len(), market.bid_book.limit_orders.len());
+	if latencies.is_empty() {
+		// nothing ran, so there is no rate or latency to report
+		return
+	}
+	println!("matching          {:.0} commands/s", per_second(latencies.len(), matching));
+	println!("latency p50       {:?}", percentile(&latencies, 0.5));
+	println!("latency p99       {:?}", percentile(&latencies, 0.99));
+	println!("latency p99.9     {:?}", percentile(&latencies, 0.999));
+	println!("latency max       {:?}", latencies[latencies.len() - 1]);
+}
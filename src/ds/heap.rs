@@ -1,7 +1,9 @@
 use std::cmp::Ord;
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap};
+#[cfg(test)]
 use std::cmp::Ordering;
-use ds::WithId;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use ds::{WithId, Queue};
 
 #[derive(Debug)]
 pub struct Heap<T: Ord + WithId> {
@@ -9,6 +11,12 @@ pub struct Heap<T: Ord + WithId> {
 	map: HashMap<u64, usize>
 }
 
+impl<T: Ord + WithId> Default for Heap<T> {
+	fn default() -> Heap<T> {
+		Heap::new()
+	}
+}
+
 impl<T: Ord + WithId> Heap<T> {
 	pub fn new() -> Heap<T> {
 		Heap {
@@ -34,7 +42,6 @@ impl<T: Ord + WithId> Heap<T> {
 
 	#[inline]
 	pub fn sift_up(&mut self, from: usize) -> usize {
-		let start = 0;
 		let mut pos = from;
 
 		while pos > 0 {
@@ -56,7 +63,7 @@ impl<T: Ord + WithId> Heap<T> {
 		while child < end {
 			let right = child + 1;
 
-			if right < end && !(self.vec[child] > self.vec[right]) {
+			if right < end && (self.vec[child] <= self.vec[right]) {
 				child = right;
 			}
 
@@ -85,18 +92,18 @@ impl<T: Ord + WithId> Heap<T> {
 	}
 
 	#[inline]
-	pub fn remove(&mut self, id: u64) {
-		let last_index = self.vec.len() - 1;
+	pub fn remove(&mut self, id: u64) -> Option<T> {
 		let idx: usize = match self.map.get(&id) {
 			Some(x) => x.to_owned(),
-			None => return
+			None => return None
 		};
+		let last_index = self.vec.len() - 1;
 		if idx == last_index {
-			self.vec.pop();
 			self.map.remove(&id);
+			self.vec.pop()
 		} else {
 			self.swap(idx, last_index);
-			self.vec.pop();
+			let elem = self.vec.pop();
 			self.map.remove(&id);
 			if idx > 0 {
 				if self.vec[self.parent(idx)] < self.vec[idx] {
@@ -107,22 +114,35 @@ impl<T: Ord + WithId> Heap<T> {
 			} else {
 				self.sift_down(0);
 			}
+			elem
 		}
 	}
 
 	#[inline]
 	pub fn peek(&self) -> Option<&T> {
-		self.vec.get(0 as usize)
+		self.vec.first()
+	}
+
+	#[inline]
+	pub fn get(&self, id: u64) -> Option<&T> {
+		self.map.get(&id).map(|idx| &self.vec[*idx])
+	}
+
+	/// Changes made through the reference must not affect the element's ordering.
+	#[inline]
+	pub fn get_mut(&mut self, id: u64) -> Option<&mut T> {
+		let vec = &mut self.vec;
+		self.map.get(&id).map(move |idx| &mut vec[*idx])
 	}
 
 	#[inline]
 	pub fn peek_mut(&mut self) -> Option<&mut T> {
-		self.vec.get_mut(0 as usize)
+		self.vec.first_mut()
 	}
 
 	#[inline]
-	pub fn pop(&mut self) {
-		let id = self.peek().unwrap().id();
+	pub fn pop(&mut self) -> Option<T> {
+		let id = self.peek()?.id();
 		self.remove(id)
 	}
 
@@ -130,38 +150,139 @@ impl<T: Ord + WithId> Heap<T> {
 	pub fn len(&self) -> usize {
 		self.vec.len()
 	}
+
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.vec.is_empty()
+	}
+
+	/// Iterates over the elements in heap order, not in priority order.
+	#[inline]
+	pub fn iter(&self) -> ::std::slice::Iter<'_, T> {
+		self.vec.iter()
+	}
+
+	/// Iterates in priority order, looking only at the elements taken and their children.
+	#[inline]
+	pub fn ordered(&self) -> impl Iterator<Item = &T> {
+		Ordered {
+			heap: self,
+			next: self.vec.first().map(|elem| (elem, 0)).into_iter().collect()
+		}
+	}
+}
+
+/// Walks a `Heap` greatest first, so taking `k` elements costs `O(k log k)` whatever its size.
+struct Ordered<'a, T: 'a + Ord + WithId> {
+	heap: &'a Heap<T>,
+	next: BinaryHeap<(&'a T, usize)>
+}
+
+impl<'a, T: Ord + WithId> Iterator for Ordered<'a, T> {
+	type Item = &'a T;
+
+	fn next(&mut self) -> Option<&'a T> {
+		let (elem, idx) = self.next.pop()?;
+		for &child in [self.heap.left(idx), self.heap.right(idx)].iter() {
+			if let Some(elem) = self.heap.vec.get(child) {
+				self.next.push((elem, child));
+			}
+		}
+		Some(elem)
+	}
+}
+
+impl<T: Ord + WithId> Queue<T> for Heap<T> {
+	fn push(&mut self, elem: T) {
+		Heap::push(self, elem)
+	}
+
+	fn peek(&self) -> Option<&T> {
+		Heap::peek(self)
+	}
+
+	fn peek_mut(&mut self) -> Option<&mut T> {
+		Heap::peek_mut(self)
+	}
+
+	fn pop(&mut self) -> Option<T> {
+		Heap::pop(self)
+	}
+
+	fn remove(&mut self, id: u64) -> Option<T> {
+		Heap::remove(self, id)
+	}
+
+	fn get(&self, id: u64) -> Option<&T> {
+		Heap::get(self, id)
+	}
+
+	fn get_mut(&mut self, id: u64) -> Option<&mut T> {
+		Heap::get_mut(self, id)
+	}
+
+	fn len(&self) -> usize {
+		Heap::len(self)
+	}
+
+	fn ordered<'a>(&'a self) -> Box<dyn Iterator<Item = &'a T> + 'a> {
+		Box::new(Heap::ordered(self))
+	}
+}
+
+/// Serialized as the sequence of its elements in heap order.
+impl<T: Ord + WithId + Serialize> Serialize for Heap<T> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(self.iter())
+	}
+}
+
+impl<'de, T: Ord + WithId + Deserialize<'de>> Deserialize<'de> for Heap<T> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Heap<T>, D::Error> {
+		let mut heap = Heap::new();
+		for element in Vec::<T>::deserialize(deserializer)? {
+			heap.push(element);
+		}
+		Ok(heap)
+	}
 }
 
+#[cfg(test)]
 #[derive(Debug)]
 struct Order {
 	id: u64,
 	price: u64
 }
 
+#[cfg(test)]
 impl WithId for Order {
 	fn id(&self) -> u64 {
 		self.id
 	}
 }
 
+#[cfg(test)]
 impl Ord for Order {
 	fn cmp(&self, other: &Order) -> Ordering {
 		self.price.cmp(&other.price)
 	}
 }
 
+#[cfg(test)]
 impl PartialOrd for Order {
 	fn partial_cmp(&self, other: &Order) -> Option<Ordering> {
-		self.price.partial_cmp(&other.price)
+		Some(self.cmp(other))
 	}
 }
 
+#[cfg(test)]
 impl PartialEq for Order {
 	fn eq(&self, other: &Order) -> bool {
 		self.price == other.price
 	}
 }
 
+#[cfg(test)]
 impl Eq for Order {}
 
 #[test]
@@ -211,10 +332,13 @@ fn test_pop() {
 	heap.push(Order{id: 5, price: 5});
 
 	assert_eq!(heap.peek().unwrap().id, 3);
-	heap.pop();
+	assert_eq!(heap.pop().unwrap().id, 3);
 	assert_eq!(heap.peek().unwrap().id, 2);
 	assert_eq!(heap.vec.len(), 4);
 	assert_eq!(heap.map.len(), 4);
+
+	let mut heap: Heap<Order> = Heap::new();
+	assert!(heap.pop().is_none());
 }
 
 #[test]
@@ -227,7 +351,7 @@ fn test_remove() {
 	heap.push(Order{id: 5, price: 5});
 
 	assert_eq!(heap.peek().unwrap().id, 3);
-	heap.remove(3);
+	assert_eq!(heap.remove(3).unwrap().id, 3);
 	assert_eq!(heap.peek().unwrap().id, 2);
 	assert_eq!(heap.vec.len(), 4);
 	assert_eq!(heap.map.len(), 4);
@@ -253,7 +377,7 @@ fn test_remove_not_existed() {
 	let mut heap = Heap::new();
 	heap.push(Order{id: 1, price: 10});
 
-	heap.remove(2);
+	assert!(heap.remove(2).is_none());
 	assert_eq!(heap.vec.len(), 1);
 	assert_eq!(heap.map.len(), 1);
 }
@@ -267,7 +391,47 @@ fn test_remove_last_one() {
 	heap.push(Order{id: 4, price: 15});
 	heap.push(Order{id: 5, price: 5});
 
-	heap.remove(5);
+	assert_eq!(heap.remove(5).unwrap().id, 5);
 	assert_eq!(heap.vec.len(), 4);
 	assert_eq!(heap.map.len(), 4);
 }
+
+#[test]
+fn test_iter() {
+	let mut heap = Heap::new();
+	heap.push(Order{id: 1, price: 10});
+	heap.push(Order{id: 2, price: 20});
+	heap.push(Order{id: 3, price: 30});
+	heap.remove(2);
+
+	let mut ids: Vec<u64> = heap.iter().map(|x| x.id).collect();
+	ids.sort();
+	assert_eq!(ids, vec![1, 3]);
+}
+
+#[test]
+fn test_get() {
+	let mut heap = Heap::new();
+	heap.push(Order{id: 1, price: 10});
+	heap.push(Order{id: 2, price: 20});
+
+	assert_eq!(heap.get(1).unwrap().price, 10);
+	assert!(heap.get(3).is_none());
+	heap.get_mut(2).unwrap().price = 20;
+	assert_eq!(heap.peek().unwrap().id, 2);
+	assert!(heap.get_mut(3).is_none());
+}
+
+#[test]
+fn test_ordered() {
+	let mut heap = Heap::new();
+	for (id, price) in [(1, 10), (2, 20), (3, 30), (4, 15), (5, 5), (6, 25), (7, 1)].iter() {
+		heap.push(Order{id: *id, price: *price});
+	}
+	heap.remove(6);
+
+	let prices: Vec<u64> = heap.ordered().map(|x| x.price).collect();
+	assert_eq!(prices, vec![30, 20, 15, 10, 5, 1]);
+	assert_eq!(heap.ordered().take(2).map(|x| x.id).collect::<Vec<u64>>(), vec![3, 2]);
+	assert!(Heap::<Order>::new().ordered().next().is_none());
+}
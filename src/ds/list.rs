@@ -1,7 +1,6 @@
-use std::cmp::Ord;
 use std::collections::HashMap;
-use std::cmp::Ordering;
-use ds::WithId;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use ds::{WithId, Queue};
 
 #[derive(Debug)]
 pub struct List<T: WithId> {
@@ -18,6 +17,29 @@ pub struct Node<T> {
 	data: T
 }
 
+pub struct Iter<'a, T: 'a + WithId> {
+	list: &'a List<T>,
+	next: Option<u64>
+}
+
+impl<'a, T: WithId> Iterator for Iter<'a, T> {
+	type Item = &'a T;
+
+	fn next(&mut self) -> Option<&'a T> {
+		let list = self.list;
+		self.next.and_then(|id| list.map.get(&id)).map(|node| {
+			self.next = node.next;
+			&node.data
+		})
+	}
+}
+
+impl<T: WithId> Default for List<T> {
+	fn default() -> List<T> {
+		List::new()
+	}
+}
+
 impl<T: WithId> List<T> {
 	pub fn new() -> List<T> {
 		List {
@@ -33,6 +55,11 @@ impl<T: WithId> List<T> {
 		self.len
 	}
 
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
 	#[inline]
 	pub fn front(&self) -> Option<&T> {
 		self.head.and_then(|id| self.map.get(&id).map(|x| &x.data))
@@ -43,6 +70,16 @@ impl<T: WithId> List<T> {
 		self.head.and_then(move |id| self.map.get_mut(&id).map(|x| &mut x.data))
 	}
 
+	#[inline]
+	pub fn get(&self, id: u64) -> Option<&T> {
+		self.map.get(&id).map(|x| &x.data)
+	}
+
+	#[inline]
+	pub fn get_mut(&mut self, id: u64) -> Option<&mut T> {
+		self.map.get_mut(&id).map(|x| &mut x.data)
+	}
+
 	#[inline]
 	pub fn push_back(&mut self, elem: T) {
 		let id = elem.id();
@@ -56,7 +93,9 @@ impl<T: WithId> List<T> {
 			self.tail = Some(id);
 		} else {
 			let tail_id = self.tail.unwrap();
-			self.map.get_mut(&tail_id).map(|x| x.next = Some(id));
+			if let Some(x) = self.map.get_mut(&tail_id) {
+				x.next = Some(id);
+			}
 			self.map.insert(id, Node{
 				prev: Some(tail_id),
 				next: None,
@@ -68,10 +107,10 @@ impl<T: WithId> List<T> {
 	}
 
 	#[inline]
-	pub fn remove(&mut self, id: u64) {
-		let (prev, next) = {
-			let node = &self.map[&id];
-			(node.prev, node.next)
+	pub fn remove(&mut self, id: u64) -> Option<T> {
+		let (prev, next) = match self.map.get(&id) {
+			Some(node) => (node.prev, node.next),
+			None => return None
 		};
 		match (prev, next) {
 			(None, None) => {
@@ -80,33 +119,107 @@ impl<T: WithId> List<T> {
 			},
 			(None, Some(next_id)) => {
 				self.head = Some(next_id);
-				self.map.get_mut(&next_id).map(|x| x.prev = None);
+				if let Some(x) = self.map.get_mut(&next_id) {
+					x.prev = None;
+				}
 			},
 			(Some(prev_id), None) => {
 				self.tail = Some(prev_id);
-				self.map.get_mut(&prev_id).map(|x| x.next = None);
+				if let Some(x) = self.map.get_mut(&prev_id) {
+					x.next = None;
+				}
 			},
 			(Some(prev_id), Some(next_id)) => {
-				self.map.get_mut(&prev_id).map(|x| x.next = Some(next_id));
-				self.map.get_mut(&next_id).map(|x| x.prev = Some(prev_id));
+				if let Some(x) = self.map.get_mut(&prev_id) {
+					x.next = Some(next_id);
+				}
+				if let Some(x) = self.map.get_mut(&next_id) {
+					x.prev = Some(prev_id);
+				}
 			}
 		}
 		self.len -= 1;
-		self.map.remove(&id);
+		self.map.remove(&id).map(|x| x.data)
 	}
 
 	#[inline]
-	pub fn pop_front(&mut self) {
-		let head = self.head.unwrap();
+	pub fn pop_front(&mut self) -> Option<T> {
+		let head = self.head?;
 		self.remove(head)
 	}
+
+	/// Iterates from front to back.
+	#[inline]
+	pub fn iter(&self) -> Iter<'_, T> {
+		Iter {
+			list: self,
+			next: self.head
+		}
+	}
+}
+
+impl<T: WithId> Queue<T> for List<T> {
+	fn push(&mut self, elem: T) {
+		List::push_back(self, elem)
+	}
+
+	fn peek(&self) -> Option<&T> {
+		List::front(self)
+	}
+
+	fn peek_mut(&mut self) -> Option<&mut T> {
+		List::front_mut(self)
+	}
+
+	fn pop(&mut self) -> Option<T> {
+		List::pop_front(self)
+	}
+
+	fn remove(&mut self, id: u64) -> Option<T> {
+		List::remove(self, id)
+	}
+
+	fn get(&self, id: u64) -> Option<&T> {
+		List::get(self, id)
+	}
+
+	fn get_mut(&mut self, id: u64) -> Option<&mut T> {
+		List::get_mut(self, id)
+	}
+
+	fn len(&self) -> usize {
+		List::len(self)
+	}
+
+	fn ordered<'a>(&'a self) -> Box<dyn Iterator<Item = &'a T> + 'a> {
+		Box::new(self.iter())
+	}
 }
 
+/// Serialized as the sequence of its elements front to back.
+impl<T: WithId + Serialize> Serialize for List<T> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(self.iter())
+	}
+}
+
+impl<'de, T: WithId + Deserialize<'de>> Deserialize<'de> for List<T> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<List<T>, D::Error> {
+		let mut list = List::new();
+		for element in Vec::<T>::deserialize(deserializer)? {
+			list.push_back(element);
+		}
+		Ok(list)
+	}
+}
+
+#[cfg(test)]
 #[derive(Debug)]
 struct Order {
 	id: u64
 }
 
+#[cfg(test)]
 impl WithId for Order {
 	fn id(&self) -> u64 {
 		self.id
@@ -114,6 +227,7 @@ impl WithId for Order {
 }
 
 #[test]
+#[allow(clippy::option_map_unit_fn)]
 fn test_push_back() {
 	let mut list = List::new();
 	list.push_back(Order{id: 1});
@@ -138,13 +252,17 @@ fn test_pop_front() {
 	list.push_back(Order{id: 1});
 	list.push_back(Order{id: 2});
 	list.push_back(Order{id: 3});
-	list.pop_front();
+	assert_eq!(list.pop_front().unwrap().id, 1);
 
 	assert_eq!(list.len(), 2);
 	assert_eq!(list.head, Some(2));
+
+	let mut list: List<Order> = List::new();
+	assert!(list.pop_front().is_none());
 }
 
 #[test]
+#[allow(clippy::option_map_unit_fn)]
 fn test_remove() {
 	//(None, None)
 	let mut list = List::new();
@@ -192,3 +310,41 @@ fn test_remove() {
 	list.map.get(&list.tail.unwrap()).map(|x| assert!(x.prev.is_some()));
 	list.map.get(&list.tail.unwrap()).and_then(|x| x.prev).map(|x| assert_eq!(x, 1));
 }
+
+#[test]
+fn test_iter() {
+	let mut list = List::new();
+	assert_eq!(list.iter().count(), 0);
+
+	list.push_back(Order{id: 1});
+	list.push_back(Order{id: 2});
+	list.push_back(Order{id: 3});
+	list.remove(2);
+	list.push_back(Order{id: 4});
+
+	let ids: Vec<u64> = list.iter().map(|x| x.id).collect();
+	assert_eq!(ids, vec![1, 3, 4]);
+}
+
+#[test]
+fn test_get() {
+	let mut list = List::new();
+	list.push_back(Order{id: 1});
+	list.push_back(Order{id: 2});
+
+	assert_eq!(list.get(2).unwrap().id, 2);
+	assert!(list.get(3).is_none());
+	assert!(list.get_mut(1).is_some());
+	assert!(list.get_mut(3).is_none());
+}
+
+#[test]
+fn test_remove_not_existed() {
+	let mut list = List::new();
+	assert!(list.remove(1).is_none());
+
+	list.push_back(Order{id: 1});
+	assert!(list.remove(2).is_none());
+	assert_eq!(list.len(), 1);
+	assert_eq!(list.remove(1).unwrap().id, 1);
+}
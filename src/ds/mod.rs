@@ -1,10 +1,48 @@
-
 mod heap;
 pub use self::heap::Heap;
 
 mod list;
 pub use self::list::List;
 
+mod tree;
+pub use self::tree::Tree;
+
 pub trait WithId {
 	fn id(&self) -> u64;
 }
+
+/// Elements served level by level, greatest level first.
+pub trait WithLevel {
+	type Level: Ord + Clone;
+
+	fn level(&self) -> Self::Level;
+}
+
+/// What matching needs from a container of resting orders: the next one to trade first,
+/// and any of them by id.
+pub trait Queue<T>: Default {
+	fn push(&mut self, elem: T);
+
+	fn peek(&self) -> Option<&T>;
+
+	/// Changes made through the reference must not affect the element's priority.
+	fn peek_mut(&mut self) -> Option<&mut T>;
+
+	fn pop(&mut self) -> Option<T>;
+
+	fn remove(&mut self, id: u64) -> Option<T>;
+
+	fn get(&self, id: u64) -> Option<&T>;
+
+	/// Changes made through the reference must not affect the element's priority.
+	fn get_mut(&mut self, id: u64) -> Option<&mut T>;
+
+	fn len(&self) -> usize;
+
+	fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Iterates in the order the elements would be popped.
+	fn ordered<'a>(&'a self) -> Box<dyn Iterator<Item = &'a T> + 'a>;
+}
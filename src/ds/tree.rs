@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use ds::{WithId, WithLevel, Queue, List};
+
+/// Elements grouped into levels kept in a balanced tree, first in first out within a level.
+/// The greatest level is served first.
+#[derive(Debug)]
+pub struct Tree<T: WithLevel + WithId> {
+	levels: BTreeMap<T::Level, List<T>>,
+	map: HashMap<u64, T::Level>
+}
+
+impl<T: WithLevel + WithId> Default for Tree<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T: WithLevel + WithId> Tree<T> {
+	pub fn new() -> Tree<T> {
+		Tree {
+			levels: BTreeMap::new(),
+			map: HashMap::new()
+		}
+	}
+
+	/// Goes to the back of its level.
+	#[inline]
+	pub fn push(&mut self, elem: T) {
+		let level = elem.level();
+		self.map.insert(elem.id(), level.clone());
+		self.levels.entry(level).or_default().push_back(elem);
+	}
+
+	#[inline]
+	pub fn remove(&mut self, id: u64) -> Option<T> {
+		let level = self.map.remove(&id)?;
+		let (elem, empty) = match self.levels.get_mut(&level) {
+			Some(list) => (list.remove(id), list.is_empty()),
+			None => return None
+		};
+		if empty {
+			self.levels.remove(&level);
+		}
+		elem
+	}
+
+	#[inline]
+	pub fn peek(&self) -> Option<&T> {
+		self.levels.values().next_back().and_then(|list| list.front())
+	}
+
+	#[inline]
+	pub fn get(&self, id: u64) -> Option<&T> {
+		self.map.get(&id).and_then(|level| self.levels.get(level)).and_then(|list| list.get(id))
+	}
+
+	/// Changes made through the reference must not affect the element's level.
+	#[inline]
+	pub fn get_mut(&mut self, id: u64) -> Option<&mut T> {
+		let levels = &mut self.levels;
+		self.map.get(&id).and_then(move |level| levels.get_mut(level)).and_then(|list| list.get_mut(id))
+	}
+
+	#[inline]
+	pub fn peek_mut(&mut self) -> Option<&mut T> {
+		self.levels.values_mut().next_back().and_then(|list| list.front_mut())
+	}
+
+	#[inline]
+	pub fn pop(&mut self) -> Option<T> {
+		let id = self.peek()?.id();
+		self.remove(id)
+	}
+
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.map.len()
+	}
+
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.map.is_empty()
+	}
+
+	/// Iterates in priority order, best level first.
+	#[inline]
+	pub fn iter(&self) -> impl Iterator<Item = &T> {
+		self.levels.values().rev().flat_map(|list| list.iter())
+	}
+}
+
+impl<T: WithLevel + WithId> Queue<T> for Tree<T> {
+	fn push(&mut self, elem: T) {
+		Tree::push(self, elem)
+	}
+
+	fn peek(&self) -> Option<&T> {
+		Tree::peek(self)
+	}
+
+	fn peek_mut(&mut self) -> Option<&mut T> {
+		Tree::peek_mut(self)
+	}
+
+	fn pop(&mut self) -> Option<T> {
+		Tree::pop(self)
+	}
+
+	fn remove(&mut self, id: u64) -> Option<T> {
+		Tree::remove(self, id)
+	}
+
+	fn get(&self, id: u64) -> Option<&T> {
+		Tree::get(self, id)
+	}
+
+	fn get_mut(&mut self, id: u64) -> Option<&mut T> {
+		Tree::get_mut(self, id)
+	}
+
+	fn len(&self) -> usize {
+		Tree::len(self)
+	}
+
+	fn ordered<'a>(&'a self) -> Box<dyn Iterator<Item = &'a T> + 'a> {
+		Box::new(self.iter())
+	}
+}
+
+/// Serialized as the sequence of its elements in priority order.
+impl<T: WithLevel + WithId + Serialize> Serialize for Tree<T> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(self.iter())
+	}
+}
+
+impl<'de, T: WithLevel + WithId + Deserialize<'de>> Deserialize<'de> for Tree<T> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tree<T>, D::Error> {
+		let mut tree = Tree::new();
+		for element in Vec::<T>::deserialize(deserializer)? {
+			tree.push(element);
+		}
+		Ok(tree)
+	}
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq)]
+struct Order {
+	id: u64,
+	price: u64
+}
+
+#[cfg(test)]
+impl WithId for Order {
+	fn id(&self) -> u64 {
+		self.id
+	}
+}
+
+#[cfg(test)]
+impl WithLevel for Order {
+	type Level = u64;
+
+	fn level(&self) -> u64 {
+		self.price
+	}
+}
+
+#[test]
+fn test_push() {
+	let mut tree = Tree::new();
+	tree.push(Order{id: 1, price: 10});
+	assert_eq!(tree.peek().unwrap().id, 1);
+	assert_eq!(tree.len(), 1);
+	tree.push(Order{id: 2, price: 20});
+	assert_eq!(tree.peek().unwrap().id, 2);
+	tree.push(Order{id: 3, price: 20});
+	assert_eq!(tree.peek().unwrap().id, 2);
+	assert_eq!(tree.len(), 3);
+	assert_eq!(tree.levels.len(), 2);
+}
+
+#[test]
+fn test_pop() {
+	let mut tree = Tree::new();
+	tree.push(Order{id: 1, price: 10});
+	tree.push(Order{id: 2, price: 20});
+	tree.push(Order{id: 3, price: 30});
+	tree.push(Order{id: 4, price: 20});
+	tree.push(Order{id: 5, price: 5});
+
+	assert_eq!(tree.pop().unwrap().id, 3);
+	assert_eq!(tree.pop().unwrap().id, 2);
+	assert_eq!(tree.pop().unwrap().id, 4);
+	assert_eq!(tree.peek().unwrap().id, 1);
+	assert_eq!(tree.len(), 2);
+	assert_eq!(tree.levels.len(), 2);
+
+	let mut tree: Tree<Order> = Tree::new();
+	assert!(tree.pop().is_none());
+}
+
+#[test]
+fn test_remove() {
+	let mut tree = Tree::new();
+	tree.push(Order{id: 1, price: 10});
+	tree.push(Order{id: 2, price: 20});
+	tree.push(Order{id: 3, price: 30});
+	tree.push(Order{id: 4, price: 20});
+
+	assert_eq!(tree.remove(3).unwrap().id, 3);
+	assert_eq!(tree.peek().unwrap().id, 2);
+	assert_eq!(tree.remove(2).unwrap().id, 2);
+	assert_eq!(tree.peek().unwrap().id, 4);
+	assert_eq!(tree.levels.len(), 2);
+	assert_eq!(tree.remove(4).unwrap().id, 4);
+	assert_eq!(tree.levels.len(), 1);
+	assert_eq!(tree.map.len(), 1);
+
+	assert!(tree.remove(4).is_none());
+	assert!(tree.remove(5).is_none());
+	assert_eq!(tree.remove(1).unwrap().id, 1);
+	assert!(tree.is_empty());
+	assert!(tree.levels.is_empty());
+	assert_eq!(tree.peek(), None);
+}
+
+#[test]
+fn test_iter() {
+	let mut tree = Tree::new();
+	tree.push(Order{id: 1, price: 10});
+	tree.push(Order{id: 2, price: 20});
+	tree.push(Order{id: 3, price: 30});
+	tree.push(Order{id: 4, price: 20});
+	tree.remove(3);
+
+	let ids: Vec<u64> = tree.iter().map(|x| x.id).collect();
+	assert_eq!(ids, vec![2, 4, 1]);
+}
+
+#[test]
+fn test_get() {
+	let mut tree = Tree::new();
+	tree.push(Order{id: 1, price: 10});
+	tree.push(Order{id: 2, price: 20});
+
+	assert_eq!(tree.get(1).unwrap().price, 10);
+	assert!(tree.get(3).is_none());
+	assert_eq!(tree.get_mut(2).unwrap().price, 20);
+	assert!(tree.get_mut(3).is_none());
+}
@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use serde_json;
+use structs::*;
+use matching::MatchResult;
+
+#[derive(Debug)]
+pub enum JournalError {
+	Io(io::Error),
+	/// The entry on this line (counted from 1) does not match its checksum or can not be decoded.
+	Corrupt(usize),
+	/// A snapshot file that is not valid JSON or does not match its checksum.
+	CorruptSnapshot,
+	/// A snapshot file written in a format this build does not read.
+	UnsupportedVersion(u32),
+	Matching(MatchingError)
+}
+
+impl fmt::Display for JournalError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			JournalError::Io(ref error) => write!(f, "journal io error: {}", error),
+			JournalError::Corrupt(line) => write!(f, "corrupt journal entry on line {}", line),
+			JournalError::CorruptSnapshot => write!(f, "corrupt snapshot"),
+			JournalError::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {}", version),
+			JournalError::Matching(ref error) => write!(f, "{}", error)
+		}
+	}
+}
+
+impl Error for JournalError {}
+
+impl From<io::Error> for JournalError {
+	fn from(error: io::Error) -> JournalError {
+		JournalError::Io(error)
+	}
+}
+
+impl From<MatchingError> for JournalError {
+	fn from(error: MatchingError) -> JournalError {
+		JournalError::Matching(error)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+	/// Position in the journal, starting at 1.
+	pub sequence: u64,
+	/// Milliseconds since the epoch when the entry was appended, zero in journals written before it was kept.
+	#[serde(default)]
+	pub time: u64,
+	pub market_id: u64,
+	pub command: Command
+}
+
+/// An append-only file of commands, each line holding the CRC-32 of an entry followed by the entry as JSON.
+pub struct Journal {
+	file: File,
+	pub sequence: u64,
+	/// Stamps the appended entries.
+	pub clock: Box<dyn Clock>
+}
+
+impl Journal {
+	/// Opens or creates the journal at `path`. A last entry torn by a crash is cut off, as its
+	/// command was never applied.
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Journal, JournalError> {
+		let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+		let mut content = String::new();
+		file.read_to_string(&mut content)?;
+		let (entries, length) = parse(&content)?;
+		file.set_len(length as u64)?;
+		Ok(Journal {
+			file,
+			sequence: entries.last().map_or(0, |entry| entry.sequence),
+			clock: Box::new(SystemClock)
+		})
+	}
+
+	/// Makes `command` durable, it has to be applied afterwards whether or not the market accepts it.
+	pub fn append(&mut self, market_id: u64, command: &Command) -> Result<JournalEntry, JournalError> {
+		let entry = JournalEntry { sequence: self.sequence + 1, time: self.clock.now(), market_id, command: command.clone() };
+		let json = serde_json::to_string(&entry).unwrap();
+		self.file.write_all(format!("{:08x} {}\n", crc32(json.as_bytes()), json).as_bytes())?;
+		self.file.sync_data()?;
+		self.sequence = entry.sequence;
+		Ok(entry)
+	}
+
+	/// Journals `command`, then applies it to `market`.
+	pub fn execute(&mut self, market: &mut Market, command: Command) -> Result<MatchResult, JournalError> {
+		self.append(market.id, &command)?;
+		Ok(market.execute(command)?)
+	}
+
+	pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<JournalEntry>, JournalError> {
+		let mut content = String::new();
+		File::open(path)?.read_to_string(&mut content)?;
+		Ok(parse(&content)?.0)
+	}
+}
+
+/// Decodes the entries of a journal, returning them with the length of the valid part.
+fn parse(content: &str) -> Result<(Vec<JournalEntry>, usize), JournalError> {
+	let mut entries = Vec::new();
+	let mut length = 0;
+	let lines: Vec<&str> = content.split_terminator('\n').collect();
+	for (index, line) in lines.iter().enumerate() {
+		let is_last = index + 1 == lines.len();
+		let entry = if is_last && !content.ends_with('\n') { None } else { parse_line(line) };
+		match entry {
+			Some(entry) => entries.push(entry),
+			None if is_last => break,
+			None => return Err(JournalError::Corrupt(index + 1))
+		}
+		length += line.len() + 1;
+	}
+	Ok((entries, length))
+}
+
+fn parse_line(line: &str) -> Option<JournalEntry> {
+	if line.len() < 9 || !line.is_char_boundary(8) || &line[8..9] != " " {
+		return None
+	}
+	let checksum = u32::from_str_radix(&line[..8], 16).ok()?;
+	let json = &line[9..];
+	if crc32(json.as_bytes()) != checksum {
+		return None
+	}
+	serde_json::from_str(json).ok()
+}
+
+/// CRC-32 (IEEE) of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+	let mut crc = !0u32;
+	for &byte in bytes {
+		crc ^= u32::from(byte);
+		for _ in 0..8 {
+			crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+		}
+	}
+	!crc
+}
+
+/// Applies `entries` to `markets` in journal order, creating the markets they name on first use.
+/// Returns every trade in the order it was made; commands refused live are refused again.
+pub fn replay(entries: &[JournalEntry], markets: &mut HashMap<u64, Market>) -> Vec<Trade> {
+	let mut trades = Vec::new();
+	for entry in entries {
+		let market = markets.entry(entry.market_id).or_insert_with(|| Market::new(entry.market_id));
+		if let Ok(result) = market.execute(entry.command.clone()) {
+			trades.extend(result.trades);
+		}
+	}
+	trades
+}
+
+/// Rebuilds every market of the journal at `path` from scratch.
+pub fn recover<P: AsRef<Path>>(path: P) -> Result<(HashMap<u64, Market>, Vec<Trade>), JournalError> {
+	let entries = Journal::read(path)?;
+	let mut markets = HashMap::new();
+	let trades = replay(&entries, &mut markets);
+	Ok((markets, trades))
+}
+
+/// Rebuilds the bars of one market from the journal, each command at the time it was appended.
+pub fn rebuild_candles(entries: &[JournalEntry], market_id: u64, intervals: Vec<Interval>) -> CandleAggregator {
+	let log: Vec<(u64, Command)> = entries.iter()
+		.filter(|entry| entry.market_id == market_id)
+		.map(|entry| (entry.time, entry.command.clone()))
+		.collect();
+	CandleAggregator::rebuild(market_id, &log, intervals)
+}
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+	pub version: u32,
+	/// Sequence of the last journal entry applied to the markets.
+	pub journal_sequence: u64,
+	pub markets: Vec<MarketState>
+}
+
+impl Snapshot {
+	pub fn new(journal_sequence: u64, markets: &HashMap<u64, Market>) -> Snapshot {
+		let mut markets: Vec<MarketState> = markets.values().map(Market::state).collect();
+		markets.sort_by_key(|state| state.id);
+		Snapshot { version: SNAPSHOT_VERSION, journal_sequence, markets }
+	}
+
+	/// Writes the snapshot next to `path` first and moves it over, so a crash leaves the
+	/// previous snapshot intact. The first line holds the CRC-32 of the rest.
+	pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), JournalError> {
+		let path = path.as_ref();
+		let json = serde_json::to_string(self).unwrap();
+		let temporary = path.with_extension("tmp");
+		let mut file = File::create(&temporary)?;
+		file.write_all(format!("{:08x}\n{}", crc32(json.as_bytes()), json).as_bytes())?;
+		file.sync_all()?;
+		fs::rename(&temporary, path)?;
+		Ok(())
+	}
+
+	pub fn read<P: AsRef<Path>>(path: P) -> Result<Snapshot, JournalError> {
+		let mut content = String::new();
+		File::open(path)?.read_to_string(&mut content)?;
+		if content.len() < 9 || !content.is_char_boundary(8) || &content[8..9] != "\n" {
+			return Err(JournalError::CorruptSnapshot)
+		}
+		let checksum = u32::from_str_radix(&content[..8], 16).map_err(|_| JournalError::CorruptSnapshot)?;
+		let json = &content[9..];
+		if crc32(json.as_bytes()) != checksum {
+			return Err(JournalError::CorruptSnapshot)
+		}
+		let value: serde_json::Value = serde_json::from_str(json).map_err(|_| JournalError::CorruptSnapshot)?;
+		let version = value["version"].as_u64().ok_or(JournalError::CorruptSnapshot)? as u32;
+		if version != SNAPSHOT_VERSION {
+			return Err(JournalError::UnsupportedVersion(version))
+		}
+		serde_json::from_value(value).map_err(|_| JournalError::CorruptSnapshot)
+	}
+
+	pub fn restore(self) -> Result<HashMap<u64, Market>, JournalError> {
+		let mut markets = HashMap::new();
+		for state in self.markets {
+			markets.insert(state.id, Market::from_state(state)?);
+		}
+		Ok(markets)
+	}
+}
+
+/// Loads the snapshot at `snapshot_path` and replays the journal entries written after it.
+/// Returns the markets with the trades of the replayed tail.
+pub fn recover_from_snapshot<P: AsRef<Path>, Q: AsRef<Path>>(snapshot_path: P, journal_path: Q) -> Result<(HashMap<u64, Market>, Vec<Trade>), JournalError> {
+	let snapshot = Snapshot::read(snapshot_path)?;
+	let journal_sequence = snapshot.journal_sequence;
+	let mut markets = snapshot.restore()?;
+	let entries: Vec<JournalEntry> = Journal::read(journal_path)?.into_iter()
+		.filter(|entry| entry.sequence > journal_sequence)
+		.collect();
+	let trades = replay(&entries, &mut markets);
+	Ok((markets, trades))
+}
+
+#[cfg(test)]
+fn journal_path(name: &str) -> ::std::path::PathBuf {
+	let path = ::std::env::temp_dir().join(format!("matching-{}-{}.journal", name, ::std::process::id()));
+	let _ = ::std::fs::remove_file(&path);
+	path
+}
+
+#[test]
+fn test_crc32() {
+	assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+}
+
+#[test]
+fn test_recover() {
+	let path = journal_path("recover");
+	let mut journal = Journal::open(&path).unwrap();
+	let mut markets = [Market::new(1), Market::new(2)];
+	let commands = vec![
+		(0, Command::Submit(Order::new(1, "5", "2", OrderKind::Limit, OrderSide::Ask).unwrap())),
+		(1, Command::Submit(Order::new(2, "6", "1", OrderKind::Limit, OrderSide::Ask).unwrap())),
+		(0, Command::Submit(Order::new(3, "4", "1", OrderKind::Limit, OrderSide::Ask).unwrap())),
+		(0, Command::Submit(Order::new(3, "4", "1", OrderKind::Limit, OrderSide::Ask).unwrap())),
+		(0, Command::Amend { id: 1, price: None, volume: Some(::rust_decimal::Decimal::new(15, 1)) }),
+		(0, Command::Submit(Order::new(4, "5", "2", OrderKind::Limit, OrderSide::Bid).unwrap())),
+		(1, Command::Cancel(2)),
+		(1, Command::Cancel(2))
+	];
+	let mut trades = Vec::new();
+	for (index, command) in commands {
+		if let Ok(result) = journal.execute(&mut markets[index], command) {
+			trades.extend(result.trades);
+		}
+	}
+	assert_eq!(journal.sequence, 8);
+
+	let (recovered, recovered_trades) = recover(&path).unwrap();
+	assert_eq!(recovered_trades, trades);
+	assert_eq!(recovered_trades.len(), 2);
+	for market in markets.iter() {
+		let snapshot = recovered[&market.id].snapshot();
+		assert_eq!(serde_json::to_string(&snapshot).unwrap(), serde_json::to_string(&market.snapshot()).unwrap());
+	}
+	let _ = ::std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_recover_configured_market() {
+	let path = journal_path("configured");
+	let mut journal = Journal::open(&path).unwrap();
+	let mut market = Market::new(1);
+	let config = MarketConfig {
+		post_only_tick: Some(::rust_decimal::Decimal::new(1, 0)),
+		self_trade_prevention: Some(SelfTradePrevention::CancelOldest),
+		reference_price: Some(::rust_decimal::Decimal::new(5, 0)),
+		market_order_remainder: MarketOrderRemainder::Cancel
+	};
+	let mut own_ask = Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	own_ask.owner = Some(7);
+	let mut own_bid = Order::new(3, "6", "2", OrderKind::Limit, OrderSide::Bid).unwrap();
+	own_bid.owner = Some(7);
+	let mut post_only = Order::new(5, "4", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	post_only.post_only = true;
+	let commands = vec![
+		Command::Configure(config.clone()),
+		Command::Submit(own_ask),
+		Command::Submit(Order::new(2, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()),
+		Command::Submit(own_bid),
+		Command::Submit(Order::new(4, "0", "1", OrderKind::Market, OrderSide::Ask).unwrap()),
+		Command::Submit(post_only),
+		Command::Submit(Order::new(6, "0", "3", OrderKind::Market, OrderSide::Ask).unwrap())
+	];
+	let mut trades = Vec::new();
+	for command in commands {
+		trades.extend(journal.execute(&mut market, command).unwrap().trades);
+	}
+
+	let (recovered, recovered_trades) = recover(&path).unwrap();
+	assert_eq!(recovered_trades, trades);
+	assert_eq!(recovered[&1].config(), config);
+	assert_eq!(recovered[&1].snapshot(), market.snapshot());
+	let _ = ::std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_rebuild_candles() {
+	let path = journal_path("candles");
+	let clock = ManualClock::new(0);
+	let mut journal = Journal::open(&path).unwrap();
+	journal.clock = Box::new(clock.clone());
+	let mut market = Market::new(1);
+	market.candles = Some(CandleAggregator::new(vec![Interval::Minute], Box::new(clock.clone())));
+	let commands = vec![
+		(1_000, Command::Submit(Order::new(1, "5", "3", OrderKind::Limit, OrderSide::Ask).unwrap())),
+		(2_000, Command::Submit(Order::new(2, "5", "1", OrderKind::Limit, OrderSide::Bid).unwrap())),
+		(61_000, Command::Submit(Order::new(3, "5", "2", OrderKind::Limit, OrderSide::Bid).unwrap()))
+	];
+	for (time, command) in commands {
+		clock.set(time);
+		journal.execute(&mut market, command).unwrap();
+	}
+	journal.append(2, &Command::Cancel(1)).unwrap();
+
+	let entries = Journal::read(&path).unwrap();
+	assert_eq!(entries.iter().map(|entry| entry.time).collect::<Vec<u64>>(), vec![1_000, 2_000, 61_000, 61_000]);
+	let mut rebuilt = rebuild_candles(&entries, 1, vec![Interval::Minute]);
+	let live = market.candles.as_mut().unwrap();
+	assert_eq!(rebuilt.current(Interval::Minute), live.current(Interval::Minute));
+	let closed = rebuilt.take_closed();
+	assert_eq!(closed.len(), 1);
+	assert_eq!(closed, live.take_closed());
+	let _ = ::std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_torn_and_corrupt_entries() {
+	let path = journal_path("torn");
+	let mut journal = Journal::open(&path).unwrap();
+	journal.append(1, &Command::Cancel(1)).unwrap();
+	journal.append(1, &Command::Cancel(2)).unwrap();
+	drop(journal);
+
+	let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+	file.write_all(b"0000").unwrap();
+	drop(file);
+	let mut journal = Journal::open(&path).unwrap();
+	assert_eq!(journal.sequence, 2);
+	journal.append(1, &Command::Cancel(3)).unwrap();
+	let entries = Journal::read(&path).unwrap();
+	assert_eq!(entries.iter().map(|entry| entry.sequence).collect::<Vec<u64>>(), vec![1, 2, 3]);
+
+	let content = ::std::fs::read_to_string(&path).unwrap().replacen("\"cancel\":1", "\"cancel\":9", 1);
+	::std::fs::write(&path, content).unwrap();
+	match Journal::read(&path) {
+		Err(JournalError::Corrupt(1)) => (),
+		result => panic!("unexpected {:?}", result.map(|entries| entries.len()))
+	}
+	let _ = ::std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_recover_from_snapshot() {
+	let path = journal_path("snapshot");
+	let snapshot_path = path.with_extension("snapshot");
+	let mut journal = Journal::open(&path).unwrap();
+	let mut markets = HashMap::new();
+	let mut market = Market::new(1);
+	market.self_trade_prevention = Some(SelfTradePrevention::CancelOldest);
+	markets.insert(1, market);
+
+	let mut iceberg = Order::new(1, "5", "3", OrderKind::Limit, OrderSide::Ask).unwrap();
+	iceberg.display_volume = Some(::rust_decimal::Decimal::new(1, 0));
+	let mut stop = Order::new(5, "0", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+	stop.trigger_price = Some(::rust_decimal::Decimal::new(4, 0));
+	let head = vec![
+		Command::Submit(iceberg),
+		Command::Submit(Order::new(2, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()),
+		Command::Submit(Order::new(3, "0", "1", OrderKind::Market, OrderSide::Bid).unwrap()),
+		Command::Submit(Order::new(4, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap()),
+		Command::Submit(stop)
+	];
+	let tail = vec![
+		Command::Submit(Order::new(6, "5", "2.5", OrderKind::Limit, OrderSide::Bid).unwrap()),
+		Command::Submit(Order::new(7, "3.5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()),
+		Command::Submit(Order::new(8, "3", "1", OrderKind::Limit, OrderSide::Ask).unwrap()),
+		Command::Cancel(2)
+	];
+	for command in head {
+		journal.execute(markets.get_mut(&1).unwrap(), command).unwrap();
+	}
+	Snapshot::new(journal.sequence, &markets).write(&snapshot_path).unwrap();
+	let mut trades = Vec::new();
+	for command in tail {
+		if let Ok(result) = journal.execute(markets.get_mut(&1).unwrap(), command) {
+			trades.extend(result.trades);
+		}
+	}
+
+	let (recovered, recovered_trades) = recover_from_snapshot(&snapshot_path, &path).unwrap();
+	assert_eq!(recovered_trades, trades);
+	assert!(trades.len() >= 3);
+	let expected = serde_json::to_string(&Snapshot::new(journal.sequence, &markets)).unwrap();
+	assert_eq!(serde_json::to_string(&Snapshot::new(journal.sequence, &recovered)).unwrap(), expected);
+
+	let content = ::std::fs::read_to_string(&snapshot_path).unwrap().replace("\"version\":1", "\"version\":2");
+	let json = &content[9..];
+	::std::fs::write(&snapshot_path, format!("{:08x}\n{}", crc32(json.as_bytes()), json)).unwrap();
+	match Snapshot::read(&snapshot_path) {
+		Err(JournalError::UnsupportedVersion(2)) => (),
+		result => panic!("unexpected {:?}", result.map(|snapshot| snapshot.version))
+	}
+	let _ = ::std::fs::remove_file(&path);
+	let _ = ::std::fs::remove_file(&snapshot_path);
+}
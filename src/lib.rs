@@ -0,0 +1,16 @@
+
+extern crate rust_decimal;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+pub mod structs;
+
+pub mod matching;
+
+pub mod ds;
+
+pub mod journal;
+
+pub mod synthetic;
@@ -1,71 +1,95 @@
-#![feature(test)]
-extern crate rust_decimal;
-use rust_decimal::Decimal;
+extern crate matching;
 extern crate serde_json;
-use serde_json::{Value, Error};
-extern crate test;
 
-use std::fs::read_to_string;
-use std::io::prelude::*;
-use std::io::BufReader;
-
-mod structs;
-use structs::*;
-
-mod matching;
-
-mod ds;
-//use ds::*;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+use matching::structs::*;
+use matching::matching::{MatchResult, RejectReason};
 
+/// Reads order log lines from the file given as the only argument, or from stdin without one,
+/// and writes what each of them caused to stdout as one JSON event per line.
 fn main() {
+	let input: Box<dyn BufRead> = match env::args().nth(1) {
+		Some(path) => match File::open(&path) {
+			Ok(file) => Box::new(BufReader::new(file)),
+			Err(error) => {
+				eprintln!("{}: {}", path, error);
+				process::exit(1)
+			}
+		},
+		None => Box::new(BufReader::new(io::stdin()))
+	};
+	let stdout = io::stdout();
+	let mut output = io::BufWriter::new(stdout.lock());
+	let mut market = Market::new(1);
 
+	for (index, line) in input.lines().enumerate() {
+		let line = match line {
+			Ok(line) => line,
+			Err(error) => {
+				eprintln!("{}", error);
+				process::exit(1)
+			}
+		};
+		if line.trim().is_empty() {
+			continue
+		}
+		let command = match serde_json::from_str::<Request>(&line) {
+			Ok(request) => Command::from(request),
+			Err(error) => {
+				eprintln!("line {}: {}", index + 1, error);
+				continue
+			}
+		};
+		// level and top of book changes are not buffered, so nothing piles up between lines
+		if let Err(error) = write_events(&mut output, events(&command, market.execute(command.clone()))) {
+			exit_on(error)
+		}
+	}
+	if let Err(error) = output.flush() {
+		exit_on(error)
+	}
 }
 
-use test::Bencher;
-#[bench]
-fn bench_matching(b: &mut Bencher) {
-	b.iter(||{
-		let mut market = Market::new(20);
+fn write_events<W: Write>(output: &mut W, events: Vec<Event>) -> io::Result<()> {
+	for event in events {
+		serde_json::to_writer(&mut *output, &event)?;
+		output.write_all(b"\n")?;
+	}
+	Ok(())
+}
 
-		let content: String = std::fs::read_to_string("/Users/cichol/etc_btc.log").unwrap();
-		let mut lines = content.lines();
+/// Stops quietly once the reader has gone away, as when piped into `head`.
+fn exit_on(error: io::Error) -> ! {
+	if error.kind() == io::ErrorKind::BrokenPipe {
+		process::exit(0)
+	}
+	eprintln!("{}", error);
+	process::exit(1)
+}
 
-		while let Some(line) = lines.next() {
-			let v: Value = serde_json::from_str(line).unwrap();
-			//println!("{:?}", line);
-			match v["action"].as_str().unwrap() {
-				"submit" => {
-					let order = Order::new(
-						v["order"]["id"].as_u64().unwrap(),
-						v["order"]["price"].as_str().unwrap(),
-						v["order"]["volume"].as_str().unwrap(),
-						OrderKind::Limit,
-						if v["order"]["type"] == "ask" {
-							OrderSide::Ask
-						} else {
-							OrderSide::Bid
-						}
-					);
-					matching::start_match(order, &mut market);
-				},
-				"cancel" => {
-					let order = Order::new(
-						v["order"]["id"].as_u64().unwrap(),
-						v["order"]["price"].as_str().unwrap(),
-						v["order"]["volume"].as_str().unwrap(),
-						OrderKind::Limit,
-						if v["order"]["type"] == "ask" {
-							OrderSide::Ask
-						} else {
-							OrderSide::Bid
-						}
-					);
-					market.cancel_order(order);
-				},
-				_ => ()
-			}
+/// What `command` did to the market, in the order it happened.
+fn events(command: &Command, result: Result<MatchResult, MatchingError>) -> Vec<Event> {
+	let id = match *command {
+		Command::Submit(ref order) => order.id,
+		Command::Cancel(id) | Command::Amend { id, .. } => id,
+		Command::Configure(_) => return Vec::new()
+	};
+	let result = match result {
+		Ok(result) => result,
+		Err(error) => return vec![Event::Rejected(id, RejectReason::Invalid(error))]
+	};
+	let mut events = Vec::new();
+	if let Command::Submit(_) = *command {
+		if !result.rejected.iter().any(|(order, _)| order.id == id) {
+			events.push(Event::Accepted(id));
 		}
-		println!("{:?}", market.ask_book.limit_orders.len());
-		println!("{:?}", market.bid_book.limit_orders.len());
-	});
+	}
+	events.extend(result.trades.into_iter().map(Event::Trade));
+	events.extend(result.self_trades.into_iter().map(Event::SelfTradePrevented));
+	events.extend(result.canceled.iter().map(|order| Event::Canceled(order.id)));
+	events.extend(result.rejected.into_iter().map(|(order, reason)| Event::Rejected(order.id, reason)));
+	events
 }
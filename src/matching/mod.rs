@@ -1,21 +1,152 @@
 
-use super::*;
+use rust_decimal::Decimal;
+use structs::*;
 use std::cmp;
+use ds::Queue;
 
-pub fn consume_limit_orders (order: &mut Order, order_book: &mut OrderBook) {
+/// Whether `order` may trade against a resting limit order at `price`, given the counter book's price requirement.
+pub fn is_price_acceptable(order: &Order, price_requirement: cmp::Ordering, price: Decimal) -> bool {
+	if order.is_unbounded() {
+		return true
+	}
+	let ordering = order.price.cmp(&price);
+	ordering == price_requirement || ordering == cmp::Ordering::Equal
+}
+
+/// Checks without touching the book whether `order` would be completely filled. Walks the
+/// crossing orders in priority order, as a self-trade that cuts the taker short fails the check.
+pub fn is_fully_fillable<L: Queue<Order>, M: Queue<Order>>(order: &Order, market: &Market<L, M>) -> bool {
+	let order_book = match order.side {
+		OrderSide::Ask => &market.bid_book,
+		OrderSide::Bid => &market.ask_book
+	};
+	let limit_orders = order_book.limit_orders.ordered()
+		.take_while(|top| is_price_acceptable(order, order_book.price_requirement, top.price));
+	let market_orders = order_book.market_orders.ordered()
+		.filter(|_| takes_market_orders(order, market));
+
+	let mut required = order.volume_remained();
+	for top_order in limit_orders.chain(market_orders) {
+		match market.self_trade_prevention {
+			Some(SelfTradePrevention::CancelOldest) if is_self_trade(order, top_order) => continue,
+			Some(_) if is_self_trade(order, top_order) => return false,
+			_ => ()
+		}
+		required -= cmp::min(required, top_order.volume_remained());
+		if required == Decimal::new(0, 0) {
+			return true
+		}
+	}
+	false
+}
+
+/// Resting market orders can only trade at a price the incoming order brings, its own limit,
+/// or the market's reference price when one is configured and within that limit.
+pub fn takes_market_orders<L: Queue<Order>, M: Queue<Order>>(order: &Order, market: &Market<L, M>) -> bool {
+	let price_requirement = match order.side {
+		OrderSide::Ask => market.bid_book.price_requirement,
+		OrderSide::Bid => market.ask_book.price_requirement
+	};
+	match market.reference_price {
+		Some(price) => is_price_acceptable(order, price_requirement, price),
+		None => order.kind == OrderKind::Limit
+	}
+}
+
+pub fn is_self_trade(order_a: &Order, order_b: &Order) -> bool {
+	order_a.owner.is_some() && order_a.owner == order_b.owner
+}
+
+/// Resolves a match between a taker and a resting order of the same owner according to `mode`,
+/// reporting it in `result`. Returns whether the taker and the maker must be canceled.
+pub fn prevent_self_trade(mode: SelfTradePrevention, taker: &mut Order, maker: &mut Order, result: &mut MatchResult) -> (bool, bool) {
+	let mut decremented = Decimal::new(0, 0);
+	let canceled = match mode {
+		SelfTradePrevention::CancelNewest => (true, false),
+		SelfTradePrevention::CancelOldest => (false, true),
+		SelfTradePrevention::CancelBoth => (true, true),
+		SelfTradePrevention::DecrementAndCancel => {
+			decremented = cmp::min(taker.volume_remained(), maker.volume_remained());
+			taker.volume -= decremented;
+			maker.volume -= decremented;
+			maker.displayed = cmp::min(maker.displayed, maker.volume_remained());
+			(taker.is_fulfilled(), maker.is_fulfilled())
+		}
+	};
+	result.self_trades.push(SelfTradePrevented {
+		mode,
+		taker_id: taker.id,
+		maker_id: maker.id,
+		decremented
+	});
+	canceled
+}
+
+/// Makes sure a post-only `order` would not take liquidity, moving it one `Market::post_only_tick`
+/// behind the best counter price if the market allows it. Returns false if the order must be rejected.
+pub fn reprice_post_only<L: Queue<Order>, M: Queue<Order>>(order: &mut Order, market: &Market<L, M>) -> bool {
+	let order_book = match order.side {
+		OrderSide::Ask => &market.bid_book,
+		OrderSide::Bid => &market.ask_book
+	};
+	if takes_market_orders(order, market) && !order_book.market_orders.is_empty() {
+		return false
+	}
+	let best_price = match order_book.limit_orders.peek() {
+		Some(top_order) if is_price_acceptable(order, order_book.price_requirement, top_order.price) => top_order.price,
+		_ => return true
+	};
+	let tick = match market.post_only_tick {
+		Some(tick) => tick,
+		None => return false
+	};
+	let price = match order.side {
+		OrderSide::Ask => best_price + tick,
+		OrderSide::Bid => best_price - tick
+	};
+	if price <= Decimal::new(0, 0) {
+		return false
+	}
+	order.price = price;
+	true
+}
+
+/// Matches `order` against the counter limit orders. Returns true if self-trade prevention
+/// canceled the rest of `order`.
+pub fn consume_limit_orders<L: Queue<Order>, M: Queue<Order>> (order: &mut Order, market: &mut Market<L, M>, result: &mut MatchResult) -> bool {
+	let order_book = match order.side {
+		OrderSide::Ask => &mut market.bid_book,
+		OrderSide::Bid => &mut market.ask_book
+	};
 	let price_requirement = order_book.price_requirement;
-	while !order.is_fulfilled() {
-		match order_book.limit_orders.peek_mut() {
-			Some(mut top_order) => {
-				let ordering = order.price.cmp(&top_order.price);
-				if ordering == price_requirement || ordering == cmp::Ordering::Equal {
-					subtract_volume(order, &mut top_order);
-				} else {
+	let prevention = market.self_trade_prevention;
+	let mut taker_canceled = false;
+	while !order.is_fulfilled() && !taker_canceled {
+		let (maker_canceled, price, reduced) = match order_book.limit_orders.peek_mut() {
+			Some(top_order) => {
+				if !is_price_acceptable(order, price_requirement, top_order.price) {
 					break
 				}
+				let displayed = top_order.volume_displayed();
+				let maker_canceled = match prevention.filter(|_| is_self_trade(order, top_order)) {
+					Some(mode) => {
+						let (taker, maker) = prevent_self_trade(mode, order, top_order, result);
+						taker_canceled = taker;
+						maker
+					},
+					None => {
+						let volume = subtract_volume(order, top_order);
+						market.trade_sequence += 1;
+						market.last_price = Some(top_order.price);
+						result.trades.push(Trade::new(market.id, market.trade_sequence, order, top_order, top_order.price, volume));
+						false
+					}
+				};
+				(maker_canceled, top_order.price, displayed - top_order.volume_displayed())
 			},
 			None => break
-		}
+		};
+		order_book.reduce_level(price, reduced);
 
 		//order_book.limit_orders.peek().map(|top|
 		//	match top.is_fulfilled() {
@@ -23,82 +154,206 @@ pub fn consume_limit_orders (order: &mut Order, order_book: &mut OrderBook) {
 		//		false => None
 		//	}
 		//).and_then(|_| order_book.limit_orders.pop());
-		if order_book.limit_orders.peek().map_or(false, |top| top.is_fulfilled()) {
-			order_book.limit_orders.pop();
+		if maker_canceled {
+			let top_order = order_book.pop_limit_order().unwrap();
+			market.order_index.remove(&top_order.id);
+			result.canceled.push(top_order);
+		} else if order_book.limit_orders.peek().is_some_and(|top| top.is_fulfilled()) {
+			let top_order = order_book.pop_limit_order().unwrap();
+			market.order_index.remove(&top_order.id);
+		} else if order_book.limit_orders.peek().is_some_and(|top| top.volume_displayed() == Decimal::new(0, 0)) {
+			// an exhausted iceberg slice is refilled and goes to the back of its price level
+			let mut top_order = order_book.pop_limit_order().unwrap();
+			market.order_sequence += 1;
+			top_order.sequence = market.order_sequence;
+			order_book.add_order(top_order).unwrap();
 		}
 	}
+	taker_canceled
 }
 
-pub fn consume_market_orders (order: &mut Order, order_book: &mut OrderBook) {
-	while !order.is_fulfilled() {
-		match order_book.market_orders.front_mut() {
-			Some(mut top_order) => {
-				subtract_volume(order, &mut top_order);
+/// Matches `order` against the counter market orders, at the market's reference price if set and
+/// at the limit of `order` otherwise. Returns true if self-trade prevention canceled the rest of `order`.
+pub fn consume_market_orders<L: Queue<Order>, M: Queue<Order>> (order: &mut Order, market: &mut Market<L, M>, result: &mut MatchResult) -> bool {
+	let order_book = match order.side {
+		OrderSide::Ask => &mut market.bid_book,
+		OrderSide::Bid => &mut market.ask_book
+	};
+	let prevention = market.self_trade_prevention;
+	let price = market.reference_price.unwrap_or(order.price);
+	let mut taker_canceled = false;
+	while !order.is_fulfilled() && !taker_canceled {
+		let maker_canceled = match order_book.market_orders.peek_mut() {
+			Some(top_order) => {
+				match prevention.filter(|_| is_self_trade(order, top_order)) {
+					Some(mode) => {
+						let (taker, maker) = prevent_self_trade(mode, order, top_order, result);
+						taker_canceled = taker;
+						maker
+					},
+					None => {
+						let volume = subtract_volume(order, top_order);
+						market.trade_sequence += 1;
+						market.last_price = Some(price);
+						result.trades.push(Trade::new(market.id, market.trade_sequence, order, top_order, price, volume));
+						false
+					}
+				}
 			}
 			None => break
-		}
-		if order_book.market_orders.front().map_or(false, |top| top.is_fulfilled()) {
-			order_book.market_orders.pop_front();
+		};
+		if maker_canceled {
+			let top_order = order_book.market_orders.pop().unwrap();
+			market.order_index.remove(&top_order.id);
+			result.canceled.push(top_order);
+		} else if order_book.market_orders.peek().is_some_and(|top| top.is_fulfilled()) {
+			let top_order = order_book.market_orders.pop().unwrap();
+			market.order_index.remove(&top_order.id);
 		}
 	}
+	taker_canceled
 }
 
-pub fn start_match (mut order: Order, market: &mut Market) {
-	let (ref mut book, ref mut counter_book) = match order.side {
-		OrderSide::Ask => (&mut market.ask_book, &mut market.bid_book),
-		OrderSide::Bid => (&mut market.bid_book, &mut market.ask_book)
-	};
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+	/// The order failed `Market::check_order`.
+	Invalid(MatchingError),
+	/// A post-only order would have taken liquidity.
+	PostOnlyWouldTake
+}
+
+/// A match that self-trade prevention stopped from becoming a trade.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SelfTradePrevented {
+	pub mode: SelfTradePrevention,
+	pub taker_id: u64,
+	pub maker_id: u64,
+	/// Volume taken off both orders, only non-zero for `SelfTradePrevention::DecrementAndCancel`.
+	pub decremented: Decimal
+}
+
+#[derive(Debug, Default)]
+pub struct MatchResult {
+	pub trades: Vec<Trade>,
+	pub self_trades: Vec<SelfTradePrevented>,
+	/// Unfilled remainders that were dropped instead of resting in the book.
+	pub canceled: Vec<Order>,
+	/// Orders refused before matching, the book is left untouched by them.
+	pub rejected: Vec<(Order, RejectReason)>
+}
+
+/// Takes a new order into `market`, matching what crosses and resting the rest as its kind and
+/// time in force allow.
+pub fn start_match<L: Queue<Order>, M: Queue<Order>> (mut order: Order, market: &mut Market<L, M>) -> Result<MatchResult, MatchingError> {
+	order.reset_progress();
+	match_order(order, market)
+}
+
+/// `start_match` for an order that keeps what it has already filled, as an amended one does.
+pub fn match_order<L: Queue<Order>, M: Queue<Order>> (mut order: Order, market: &mut Market<L, M>) -> Result<MatchResult, MatchingError> {
+	if let Err(error) = market.check_order(&order) {
+		market.notify(|listener| listener.on_rejected(&order, &RejectReason::Invalid(error.clone())));
+		return Err(error)
+	}
+	order.sequence = market.next_sequence();
+	market.notify(|listener| listener.on_accepted(&order));
+	let mut result = MatchResult::default();
 	match order.kind {
-		OrderKind::Limit => {
-			consume_limit_orders(&mut order, counter_book);
-			consume_market_orders(&mut order, counter_book)
+		OrderKind::Stop | OrderKind::StopLimit if !market.last_price.is_some_and(|price| order.is_triggered(price)) => {
+			market.rest_order(order)?
 		},
-		OrderKind::Market => {
-			consume_limit_orders(&mut order, counter_book)
+		_ => execute_order(order, market, &mut result)?
+	}
+
+	// every trade moves the last price, which may release stop orders that trade in turn
+	while let Some(mut order) = market.pop_triggered() {
+		order.sequence = market.next_sequence();
+		execute_order(order, market, &mut result)?;
+	}
+	if let Some(ref mut candles) = market.candles {
+		for trade in result.trades.iter() {
+			candles.record(trade);
 		}
 	}
+	market.notify_result(&result);
+	market.publish_levels();
+	Ok(result)
+}
 
-	if !order.is_fulfilled() {
+fn execute_order<L: Queue<Order>, M: Queue<Order>> (mut order: Order, market: &mut Market<L, M>, result: &mut MatchResult) -> Result<(), MatchingError> {
+	order.activate();
+	if order.time_in_force == TimeInForce::FillOrKill && !is_fully_fillable(&order, market) {
+		result.canceled.push(order);
+		return Ok(())
+	}
+	if order.post_only && !reprice_post_only(&mut order, market) {
+		result.rejected.push((order, RejectReason::PostOnlyWouldTake));
+		return Ok(())
+	}
+	let taker_canceled = consume_limit_orders(&mut order, market, result) ||
+		(takes_market_orders(&order, market) && consume_market_orders(&mut order, market, result));
+
+	if taker_canceled {
+		result.canceled.push(order);
+	} else if !order.is_fulfilled() {
 		//println!("fulfilled");
-		book.add_order(order);
+		match (order.time_in_force, order.kind, market.market_order_remainder) {
+			(TimeInForce::ImmediateOrCancel, _, _) | (TimeInForce::FillOrKill, _, _) => result.canceled.push(order),
+			(_, OrderKind::Market, MarketOrderRemainder::Cancel) => result.canceled.push(order),
+			(_, OrderKind::Market, MarketOrderRemainder::ConvertToLimit) => match market.last_price {
+				Some(price) => {
+					order.kind = OrderKind::Limit;
+					order.price = price;
+					return execute_order(order, market, result)
+				},
+				None => result.canceled.push(order)
+			},
+			_ => market.rest_order(order)?
+		}
 	}
+	Ok(())
 }
 
-pub fn subtract_volume(order_a: &mut Order, order_b: &mut Order) -> (bool, bool) {
-	let min_volume = cmp::min(order_a.volume_remained(), order_b.volume_remained());
+/// Fills `order_a` against the visible volume of the resting `order_b`.
+pub fn subtract_volume(order_a: &mut Order, order_b: &mut Order) -> Decimal {
+	let min_volume = cmp::min(order_a.volume_remained(), order_b.volume_displayed());
 	order_a.filled += min_volume;
 	order_b.filled += min_volume;
-	// broadcast trade
-	//println!("deal");
-	(order_a.is_fulfilled(), order_b.is_fulfilled())
+	if order_b.is_iceberg() {
+		order_b.displayed -= min_volume;
+	}
+	min_volume
 }
 
 #[test]
 fn test_subtract_volume() {
-	let mut order_a = Order::new(1, "1", "2", OrderKind::Limit, OrderSide::Bid);
-	let mut order_b = Order::new(2, "1", "3", OrderKind::Limit, OrderSide::Ask);
+	let mut order_a = Order::new(1, "1", "2", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let mut order_b = Order::new(2, "1", "3", OrderKind::Limit, OrderSide::Ask).unwrap();
 
-	let (a_fulfilled, b_fulfilled) = subtract_volume(&mut order_a, &mut order_b);
+	let volume = subtract_volume(&mut order_a, &mut order_b);
+	assert_eq!(volume, Decimal::new(2, 0));
 	assert_eq!(order_a.filled, Decimal::new(2, 0));
 	assert_eq!(order_b.filled, Decimal::new(2, 0));
-	assert!(a_fulfilled);
+	assert!(order_a.is_fulfilled());
+	assert!(!order_b.is_fulfilled());
 }
 
 #[test]
 fn test_add_orders() {
 	let mut market = Market::new(1);
 
-	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_b = Order::new(2, "2", "1", OrderKind::Limit, OrderSide::Bid);
+	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_b = Order::new(2, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
 
-	start_match(order_a, &mut market);
-	start_match(order_b, &mut market);
+	start_match(order_a, &mut market).unwrap();
+	start_match(order_b, &mut market).unwrap();
 
-	let order_c = Order::new(3, "3", "1", OrderKind::Market, OrderSide::Bid);
-	let order_d = Order::new(4, "4", "1", OrderKind::Market, OrderSide::Bid);
+	let order_c = Order::new(3, "3", "1", OrderKind::Market, OrderSide::Bid).unwrap();
+	let order_d = Order::new(4, "4", "1", OrderKind::Market, OrderSide::Bid).unwrap();
 
-	start_match(order_c, &mut market);
-	start_match(order_d, &mut market);
+	start_match(order_c, &mut market).unwrap();
+	start_match(order_d, &mut market).unwrap();
 
 	assert_eq!(market.bid_book.limit_orders.len(), 2);
 	assert_eq!(market.bid_book.market_orders.len(), 2);
@@ -108,19 +363,19 @@ fn test_add_orders() {
 fn test_matching_limit_order() {
 	let mut market = Market::new(1);
 
-	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_b = Order::new(2, "2", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_c = Order::new(3, "3", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_d = Order::new(4, "4", "1", OrderKind::Limit, OrderSide::Bid);
+	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_b = Order::new(2, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_c = Order::new(3, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_d = Order::new(4, "4", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
 
-	market.add_order(order_a);
-	market.add_order(order_b);
-	market.add_order(order_c);
-	market.add_order(order_d);
+	market.add_order(order_a).unwrap();
+	market.add_order(order_b).unwrap();
+	market.add_order(order_c).unwrap();
+	market.add_order(order_d).unwrap();
 
-	let order = Order::new(5, "3", "3", OrderKind::Limit, OrderSide::Ask);
+	let order = Order::new(5, "3", "3", OrderKind::Limit, OrderSide::Ask).unwrap();
 
-	start_match(order, &mut market);
+	start_match(order, &mut market).unwrap();
 
 	assert_eq!(market.ask_book.limit_orders.peek().unwrap().id, 5);
 	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 2);
@@ -129,19 +384,19 @@ fn test_matching_limit_order() {
 
 	let mut market = Market::new(1);
 
-	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_b = Order::new(2, "2", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_c = Order::new(3, "3", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_d = Order::new(4, "4", "1", OrderKind::Limit, OrderSide::Bid);
+	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_b = Order::new(2, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_c = Order::new(3, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_d = Order::new(4, "4", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
 
-	market.add_order(order_a);
-	market.add_order(order_b);
-	market.add_order(order_c);
-	market.add_order(order_d);
+	market.add_order(order_a).unwrap();
+	market.add_order(order_b).unwrap();
+	market.add_order(order_c).unwrap();
+	market.add_order(order_d).unwrap();
 
-	let order = Order::new(5, "3", "1", OrderKind::Limit, OrderSide::Ask);
+	let order = Order::new(5, "3", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
 
-	start_match(order, &mut market);
+	start_match(order, &mut market).unwrap();
 
 	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 3);
 	assert_eq!(market.ask_book.limit_orders.peek(), None);
@@ -152,19 +407,19 @@ fn test_matching_limit_order() {
 fn test_matching_market_order() {
 	let mut market = Market::new(1);
 
-	let order_a = Order::new(1, "1", "1", OrderKind::Market, OrderSide::Bid);
-	let order_b = Order::new(2, "1", "1", OrderKind::Market, OrderSide::Bid);
-	let order_c = Order::new(3, "1", "1", OrderKind::Market, OrderSide::Bid);
-	let order_d = Order::new(4, "1", "1", OrderKind::Market, OrderSide::Bid);
+	let order_a = Order::new(1, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap();
+	let order_b = Order::new(2, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap();
+	let order_c = Order::new(3, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap();
+	let order_d = Order::new(4, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap();
 
-	market.add_order(order_a);
-	market.add_order(order_b);
-	market.add_order(order_c);
-	market.add_order(order_d);
+	market.add_order(order_a).unwrap();
+	market.add_order(order_b).unwrap();
+	market.add_order(order_c).unwrap();
+	market.add_order(order_d).unwrap();
 
-	let order = Order::new(5, "3", "3", OrderKind::Limit, OrderSide::Ask);
+	let order = Order::new(5, "3", "3", OrderKind::Limit, OrderSide::Ask).unwrap();
 
-	start_match(order, &mut market);
+	start_match(order, &mut market).unwrap();
 
 	assert_eq!(market.bid_book.market_orders.len(), 1);
 
@@ -172,9 +427,9 @@ fn test_matching_market_order() {
 	assert_eq!(market.bid_book.market_orders.len(), 1);
 	assert_eq!(market.ask_book.limit_orders.peek(), None);
 
-	let order = Order::new(5, "3", "3", OrderKind::Market, OrderSide::Ask);
+	let order = Order::new(5, "3", "3", OrderKind::Market, OrderSide::Ask).unwrap();
 
-	start_match(order, &mut market);
+	start_match(order, &mut market).unwrap();
 
 	assert_eq!(market.bid_book.market_orders.front().unwrap().id, 4);
 	assert_eq!(market.bid_book.market_orders.len(), 1);
@@ -185,22 +440,592 @@ fn test_matching_market_order() {
 fn test_matching_limit_order_then_market_other() {
 	let mut market = Market::new(1);
 
-	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_b = Order::new(2, "1", "1", OrderKind::Market, OrderSide::Bid);
-	let order_c = Order::new(3, "3", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_d = Order::new(4, "4", "1", OrderKind::Limit, OrderSide::Bid);
+	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_b = Order::new(2, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap();
+	let order_c = Order::new(3, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_d = Order::new(4, "4", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
 
-	market.add_order(order_a);
-	market.add_order(order_b);
-	market.add_order(order_c);
-	market.add_order(order_d);
+	market.add_order(order_a).unwrap();
+	market.add_order(order_b).unwrap();
+	market.add_order(order_c).unwrap();
+	market.add_order(order_d).unwrap();
 
-	let order = Order::new(5, "3", "2.5", OrderKind::Limit, OrderSide::Ask);
+	let order = Order::new(5, "3", "2.5", OrderKind::Limit, OrderSide::Ask).unwrap();
 
-	start_match(order, &mut market);
+	start_match(order, &mut market).unwrap();
 
 	assert_eq!(market.bid_book.limit_orders.len(), 1);
 	assert_eq!(market.bid_book.market_orders.len(), 1);
 	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 1);
 	assert_eq!(market.bid_book.market_orders.front().unwrap().filled, Decimal::new(5, 1));
 }
+
+#[test]
+fn test_trades_emitted() {
+	let mut market = Market::new(9);
+
+	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_b = Order::new(2, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_c = Order::new(3, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap();
+
+	market.add_order(order_a).unwrap();
+	market.add_order(order_b).unwrap();
+	market.add_order(order_c).unwrap();
+
+	let order = Order::new(4, "1", "2.5", OrderKind::Limit, OrderSide::Ask).unwrap();
+	let trades = start_match(order, &mut market).unwrap().trades;
+
+	assert_eq!(trades.len(), 3);
+	assert_eq!(trades[0], Trade {
+		market_id: 9,
+		sequence: 1,
+		maker_id: 2,
+		taker_id: 4,
+		price: Decimal::new(2, 0),
+		volume: Decimal::new(1, 0),
+		taker_side: OrderSide::Ask,
+		maker_remaining: Decimal::new(0, 0),
+		taker_remaining: Decimal::new(15, 1)
+	});
+	assert_eq!(trades[1].maker_id, 1);
+	assert_eq!(trades[1].price, Decimal::new(1, 0));
+	assert_eq!(trades[1].sequence, 2);
+	assert_eq!(trades[2].maker_id, 3);
+	assert_eq!(trades[2].price, Decimal::new(1, 0));
+	assert_eq!(trades[2].volume, Decimal::new(5, 1));
+	assert_eq!(market.trade_sequence, 3);
+
+	let order = Order::new(5, "3", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	let trades = start_match(order, &mut market).unwrap().trades;
+	assert_eq!(trades.len(), 1);
+	assert_eq!(trades[0].maker_id, 3);
+	assert_eq!(trades[0].price, Decimal::new(3, 0));
+	assert_eq!(trades[0].sequence, 4);
+
+	let order = Order::new(6, "3", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	assert!(start_match(order, &mut market).unwrap().trades.is_empty());
+}
+
+#[test]
+fn test_fifo_within_price_level() {
+	let mut market = Market::new(1);
+
+	for id in 1..9 {
+		let price = if id % 2 == 0 { "2" } else { "1" };
+		start_match(Order::new(id, price, "1", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	}
+
+	let order = Order::new(9, "1", "8", OrderKind::Limit, OrderSide::Ask).unwrap();
+	let trades = start_match(order, &mut market).unwrap().trades;
+	let makers: Vec<u64> = trades.iter().map(|trade| trade.maker_id).collect();
+	assert_eq!(makers, vec![2, 4, 6, 8, 1, 3, 5, 7]);
+
+	let mut market = Market::new(1);
+
+	for id in 1..9 {
+		let price = if id % 2 == 0 { "1" } else { "2" };
+		start_match(Order::new(id, price, "1", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	}
+
+	let order = Order::new(9, "2", "8", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let trades = start_match(order, &mut market).unwrap().trades;
+	let makers: Vec<u64> = trades.iter().map(|trade| trade.maker_id).collect();
+	assert_eq!(makers, vec![2, 4, 6, 8, 1, 3, 5, 7]);
+}
+
+#[test]
+fn test_fifo_survives_partial_fills_and_cancels() {
+	let mut market = Market::new(1);
+
+	start_match(Order::new(1, "1", "2", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	start_match(Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	start_match(Order::new(3, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	start_match(Order::new(4, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+
+	let trades = start_match(Order::new(5, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap().trades;
+	assert_eq!(trades[0].maker_id, 1);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 1);
+
+	assert_eq!(market.cancel_order(2).unwrap().id, 2);
+
+	let trades = start_match(Order::new(6, "1", "3", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap().trades;
+	let makers: Vec<u64> = trades.iter().map(|trade| trade.maker_id).collect();
+	assert_eq!(makers, vec![1, 3, 4]);
+	assert_eq!(market.bid_book.limit_orders.len(), 0);
+}
+
+#[test]
+fn test_immediate_or_cancel() {
+	let mut market = Market::new(1);
+
+	market.add_order(Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(2, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+
+	let mut order = Order::new(3, "2", "3", OrderKind::Limit, OrderSide::Ask).unwrap();
+	order.time_in_force = TimeInForce::ImmediateOrCancel;
+
+	let result = start_match(order, &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(result.trades[0].maker_id, 2);
+
+	let canceled = &result.canceled[0];
+	assert_eq!(canceled.id, 3);
+	assert_eq!(canceled.filled, Decimal::new(1, 0));
+	assert_eq!(canceled.volume_remained(), Decimal::new(2, 0));
+	assert_eq!(market.ask_book.limit_orders.len(), 0);
+	assert_eq!(market.bid_book.limit_orders.len(), 1);
+
+	let mut order = Order::new(4, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	order.time_in_force = TimeInForce::ImmediateOrCancel;
+
+	let result = start_match(order, &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert!(result.canceled.is_empty());
+
+	let mut order = Order::new(5, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap();
+	order.time_in_force = TimeInForce::ImmediateOrCancel;
+
+	let result = start_match(order, &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	assert_eq!(result.canceled[0].id, 5);
+	assert_eq!(market.bid_book.market_orders.len(), 0);
+}
+
+#[test]
+fn test_fill_or_kill() {
+	let mut market = Market::new(1);
+
+	market.add_order(Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(2, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(3, "1", "0.5", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+
+	let mut order = Order::new(4, "2", "2", OrderKind::Limit, OrderSide::Ask).unwrap();
+	order.time_in_force = TimeInForce::FillOrKill;
+
+	let result = start_match(order, &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	assert_eq!(result.canceled[0].filled, Decimal::new(0, 0));
+	assert_eq!(market.bid_book.limit_orders.len(), 2);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().filled, Decimal::new(0, 0));
+	assert_eq!(market.bid_book.market_orders.front().unwrap().filled, Decimal::new(0, 0));
+	assert_eq!(market.ask_book.limit_orders.len(), 0);
+
+	let mut order = Order::new(5, "1", "1.5", OrderKind::Market, OrderSide::Ask).unwrap();
+	order.time_in_force = TimeInForce::FillOrKill;
+
+	let result = start_match(order, &mut market).unwrap();
+	assert_eq!(result.trades.len(), 2);
+	assert!(result.canceled.is_empty());
+	assert_eq!(market.bid_book.limit_orders.len(), 1);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().volume_remained(), Decimal::new(5, 1));
+
+	let mut order = Order::new(6, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	order.time_in_force = TimeInForce::FillOrKill;
+
+	let result = start_match(order, &mut market).unwrap();
+	assert_eq!(result.trades.len(), 2);
+	assert_eq!(result.trades[1].maker_id, 3);
+	assert!(result.canceled.is_empty());
+	assert_eq!(market.bid_book.limit_orders.len(), 0);
+	assert_eq!(market.bid_book.market_orders.len(), 0);
+	assert_eq!(market.ask_book.limit_orders.len(), 0);
+}
+
+#[test]
+fn test_post_only_rejected() {
+	let mut market = Market::new(1);
+
+	market.add_order(Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+
+	let mut order = Order::new(2, "1.5", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	order.post_only = true;
+	let result = start_match(order, &mut market).unwrap();
+	assert!(result.rejected.is_empty());
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 2);
+
+	let mut order = Order::new(3, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	order.post_only = true;
+	let result = start_match(order, &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	let (ref rejected, ref reason) = result.rejected[0];
+	assert_eq!(rejected.id, 3);
+	assert_eq!(*reason, RejectReason::PostOnlyWouldTake);
+	assert_eq!(market.ask_book.limit_orders.peek().unwrap().filled, Decimal::new(0, 0));
+	assert_eq!(market.bid_book.limit_orders.len(), 1);
+
+	market.add_order(Order::new(4, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+	market.post_only_tick = Some(Decimal::new(1, 1));
+
+	let mut order = Order::new(5, "3", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	order.post_only = true;
+	let result = start_match(order, &mut market).unwrap();
+	assert_eq!(result.rejected[0].1, RejectReason::PostOnlyWouldTake);
+	assert_eq!(market.bid_book.market_orders.len(), 1);
+}
+
+#[test]
+fn test_post_only_repriced() {
+	let mut market = Market::new(1);
+	market.post_only_tick = Some(Decimal::new(1, 1));
+
+	market.add_order(Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+
+	let mut order = Order::new(3, "2.5", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	order.post_only = true;
+	let result = start_match(order, &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	assert!(result.rejected.is_empty());
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 3);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().price, Decimal::new(19, 1));
+
+	let mut order = Order::new(4, "0.5", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	order.post_only = true;
+	start_match(order, &mut market).unwrap();
+	assert_eq!(market.ask_book.limit_orders.peek().unwrap().id, 1);
+	assert_eq!(market.ask_book.limit_orders.iter().find(|x| x.id == 4).unwrap().price, Decimal::new(2, 0));
+	assert_eq!(market.ask_book.limit_orders.len(), 2);
+}
+
+#[test]
+fn test_stop_orders_wait_for_trigger() {
+	let mut market = Market::new(1);
+
+	market.add_order(Order::new(1, "5", "2", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+
+	let mut order = Order::new(2, "5", "1", OrderKind::StopLimit, OrderSide::Bid).unwrap();
+	order.trigger_price = Some(Decimal::new(4, 0));
+	let result = start_match(order, &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	assert_eq!(market.bid_triggers.len(), 1);
+	assert_eq!(market.bid_book.limit_orders.len(), 0);
+
+	market.add_order(Order::new(3, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	let result = start_match(Order::new(4, "3", "1", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(market.last_price, Some(Decimal::new(3, 0)));
+	assert_eq!(market.bid_triggers.len(), 1);
+
+	let result = start_match(Order::new(5, "5", "0.5", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 2);
+	assert_eq!(result.trades[0].taker_id, 5);
+	assert_eq!(result.trades[1].taker_id, 2);
+	assert_eq!(result.trades[1].maker_id, 1);
+	assert_eq!(result.trades[1].volume, Decimal::new(1, 0));
+	assert_eq!(market.bid_triggers.len(), 0);
+	assert_eq!(market.ask_book.limit_orders.peek().unwrap().volume_remained(), Decimal::new(5, 1));
+}
+
+#[test]
+fn test_stop_order_triggered_on_arrival() {
+	let mut market = Market::new(1);
+
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	start_match(Order::new(2, "5", "0.5", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+
+	let mut order = Order::new(3, "1", "0.5", OrderKind::Stop, OrderSide::Ask).unwrap();
+	order.trigger_price = Some(Decimal::new(6, 0));
+	let result = start_match(order, &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(result.trades[0].taker_id, 3);
+	assert_eq!(market.ask_triggers.len(), 0);
+	assert_eq!(market.bid_book.limit_orders.len(), 0);
+}
+
+#[test]
+fn test_buy_stop_order_triggered() {
+	let mut market = Market::new(1);
+
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(2, "7", "2", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+
+	let mut order = Order::new(3, "0", "1.5", OrderKind::Stop, OrderSide::Bid).unwrap();
+	order.trigger_price = Some(Decimal::new(5, 0));
+	let result = start_match(order, &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	assert_eq!(market.bid_triggers.len(), 1);
+
+	let result = start_match(Order::new(4, "5", "0.5", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 3);
+	assert_eq!((result.trades[1].taker_id, result.trades[1].maker_id, result.trades[1].volume), (3, 1, Decimal::new(5, 1)));
+	assert_eq!((result.trades[2].taker_id, result.trades[2].maker_id, result.trades[2].price), (3, 2, Decimal::new(7, 0)));
+	assert_eq!(market.bid_triggers.len(), 0);
+	assert_eq!(market.bid_book.market_orders.len(), 0);
+	assert_eq!(market.ask_book.limit_orders.peek().unwrap().volume_remained(), Decimal::new(1, 0));
+}
+
+#[test]
+fn test_stop_order_cascade() {
+	let mut market = Market::new(1);
+
+	market.add_order(Order::new(1, "10", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(2, "9", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(3, "8", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(4, "7", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+
+	let mut order = Order::new(5, "1", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+	order.trigger_price = Some(Decimal::new(9, 0));
+	start_match(order, &mut market).unwrap();
+	let mut order = Order::new(6, "1", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+	order.trigger_price = Some(Decimal::new(8, 0));
+	start_match(order, &mut market).unwrap();
+	let mut order = Order::new(7, "1", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+	order.trigger_price = Some(Decimal::new(5, 0));
+	start_match(order, &mut market).unwrap();
+	let mut order = Order::new(8, "9", "1", OrderKind::StopLimit, OrderSide::Ask).unwrap();
+	order.trigger_price = Some(Decimal::new(7, 0));
+	order.time_in_force = TimeInForce::ImmediateOrCancel;
+	start_match(order, &mut market).unwrap();
+	assert_eq!(market.ask_triggers.len(), 4);
+
+	let result = start_match(Order::new(9, "10", "1", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(market.ask_triggers.len(), 4);
+
+	let result = start_match(Order::new(10, "9", "1", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	let takers: Vec<u64> = result.trades.iter().map(|trade| trade.taker_id).collect();
+	let prices: Vec<Decimal> = result.trades.iter().map(|trade| trade.price).collect();
+	assert_eq!(takers, vec![10, 5, 6]);
+	assert_eq!(prices, vec![Decimal::new(9, 0), Decimal::new(8, 0), Decimal::new(7, 0)]);
+	assert_eq!(result.canceled.len(), 1);
+	assert_eq!(result.canceled[0].id, 8);
+	assert_eq!(market.ask_triggers.len(), 1);
+	assert_eq!(market.bid_book.limit_orders.len(), 0);
+}
+
+#[test]
+fn test_iceberg_order() {
+	let mut market = Market::new(1);
+
+	let mut order = Order::new(1, "1", "5", OrderKind::Limit, OrderSide::Bid).unwrap();
+	order.display_volume = Some(Decimal::new(2, 0));
+	start_match(order, &mut market).unwrap();
+	market.add_order(Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(3, "0.5", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().volume_displayed(), Decimal::new(2, 0));
+
+	let result = start_match(Order::new(4, "1", "1.5", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(result.trades[0].maker_id, 1);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 1);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().volume_displayed(), Decimal::new(5, 1));
+
+	let result = start_match(Order::new(5, "1", "4", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	let makers: Vec<(u64, Decimal)> = result.trades.iter().map(|trade| (trade.maker_id, trade.volume)).collect();
+	assert_eq!(makers, vec![
+		(1, Decimal::new(5, 1)),
+		(2, Decimal::new(1, 0)),
+		(1, Decimal::new(2, 0)),
+		(1, Decimal::new(5, 1))
+	]);
+	assert!(market.ask_book.limit_orders.peek().is_none());
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 1);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().volume_remained(), Decimal::new(5, 1));
+	assert_eq!(market.bid_book.limit_orders.len(), 2);
+}
+
+#[test]
+fn test_start_match_errors() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+
+	let order = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	assert_eq!(start_match(order, &mut market).unwrap_err(), MatchingError::DuplicateOrder(1));
+
+	let order = Order::new(2, "1", "0", OrderKind::Limit, OrderSide::Bid).unwrap();
+	assert_eq!(start_match(order, &mut market).unwrap_err(), MatchingError::InvalidVolume(2));
+
+	let order = Order::new(3, "1", "1", OrderKind::Stop, OrderSide::Bid).unwrap();
+	assert_eq!(start_match(order, &mut market).unwrap_err(), MatchingError::InvalidTriggerPrice(3));
+
+	assert_eq!(market.ask_book.limit_orders.peek().unwrap().filled, Decimal::new(0, 0));
+	assert_eq!(market.bid_book.limit_orders.len(), 0);
+	assert_eq!(market.bid_triggers.len(), 0);
+	assert_eq!(market.trade_sequence, 0);
+}
+
+#[cfg(test)]
+fn owned_order(id: u64, owner: u64, price: &str, volume: &str, side: OrderSide) -> Order {
+	let mut order = Order::new(id, price, volume, OrderKind::Limit, side).unwrap();
+	order.owner = Some(owner);
+	order
+}
+
+#[test]
+fn test_self_trade_allowed_by_default() {
+	let mut market = Market::new(1);
+	market.add_order(owned_order(1, 7, "1", "1", OrderSide::Bid)).unwrap();
+
+	let result = start_match(owned_order(2, 7, "1", "1", OrderSide::Ask), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert!(result.self_trades.is_empty());
+}
+
+#[test]
+fn test_self_trade_cancel_newest() {
+	let mut market = Market::new(1);
+	market.self_trade_prevention = Some(SelfTradePrevention::CancelNewest);
+	market.add_order(owned_order(1, 8, "2", "1", OrderSide::Bid)).unwrap();
+	market.add_order(owned_order(2, 7, "1", "1", OrderSide::Bid)).unwrap();
+	market.add_order(owned_order(3, 8, "1", "1", OrderSide::Bid)).unwrap();
+
+	let result = start_match(owned_order(4, 7, "1", "3", OrderSide::Ask), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(result.trades[0].maker_id, 1);
+	assert_eq!(result.self_trades, vec![SelfTradePrevented {
+		mode: SelfTradePrevention::CancelNewest,
+		taker_id: 4,
+		maker_id: 2,
+		decremented: Decimal::new(0, 0)
+	}]);
+	assert_eq!(result.canceled.len(), 1);
+	assert_eq!(result.canceled[0].id, 4);
+	assert_eq!(result.canceled[0].filled, Decimal::new(1, 0));
+	assert_eq!(market.bid_book.limit_orders.len(), 2);
+	assert_eq!(market.ask_book.limit_orders.len(), 0);
+}
+
+#[test]
+fn test_self_trade_cancel_oldest() {
+	let mut market = Market::new(1);
+	market.self_trade_prevention = Some(SelfTradePrevention::CancelOldest);
+	market.add_order(owned_order(1, 7, "1", "1", OrderSide::Bid)).unwrap();
+	market.add_order(owned_order(2, 8, "1", "1", OrderSide::Bid)).unwrap();
+	let mut order = Order::new(3, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap();
+	order.owner = Some(7);
+	market.add_order(order).unwrap();
+
+	let result = start_match(owned_order(4, 7, "1", "2", OrderSide::Ask), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(result.trades[0].maker_id, 2);
+	let makers: Vec<u64> = result.self_trades.iter().map(|prevented| prevented.maker_id).collect();
+	assert_eq!(makers, vec![1, 3]);
+	let canceled: Vec<u64> = result.canceled.iter().map(|order| order.id).collect();
+	assert_eq!(canceled, vec![1, 3]);
+	assert_eq!(market.ask_book.limit_orders.peek().unwrap().id, 4);
+	assert_eq!(market.bid_book.market_orders.len(), 0);
+	assert!(market.cancel_order(1).is_err());
+}
+
+#[test]
+fn test_self_trade_cancel_both() {
+	let mut market = Market::new(1);
+	market.self_trade_prevention = Some(SelfTradePrevention::CancelBoth);
+	market.add_order(owned_order(1, 7, "1", "1", OrderSide::Bid)).unwrap();
+	market.add_order(owned_order(2, 8, "1", "1", OrderSide::Bid)).unwrap();
+
+	let result = start_match(owned_order(3, 7, "1", "2", OrderSide::Ask), &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	assert_eq!(result.self_trades.len(), 1);
+	let canceled: Vec<u64> = result.canceled.iter().map(|order| order.id).collect();
+	assert_eq!(canceled, vec![1, 3]);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 2);
+	assert_eq!(market.ask_book.limit_orders.len(), 0);
+}
+
+#[test]
+fn test_self_trade_decrement_and_cancel() {
+	let mut market = Market::new(1);
+	market.self_trade_prevention = Some(SelfTradePrevention::DecrementAndCancel);
+	market.add_order(owned_order(1, 7, "1", "1", OrderSide::Bid)).unwrap();
+	market.add_order(owned_order(2, 8, "1", "1", OrderSide::Bid)).unwrap();
+	market.add_order(owned_order(3, 7, "1", "5", OrderSide::Bid)).unwrap();
+
+	let result = start_match(owned_order(4, 7, "1", "3", OrderSide::Ask), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(result.trades[0].maker_id, 2);
+	assert_eq!(result.self_trades.len(), 2);
+	assert_eq!(result.self_trades[0].maker_id, 1);
+	assert_eq!(result.self_trades[0].decremented, Decimal::new(1, 0));
+	assert_eq!(result.self_trades[1].maker_id, 3);
+	assert_eq!(result.self_trades[1].decremented, Decimal::new(1, 0));
+	let canceled: Vec<u64> = result.canceled.iter().map(|order| order.id).collect();
+	assert_eq!(canceled, vec![1, 4]);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 3);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().volume_remained(), Decimal::new(4, 0));
+	assert_eq!(market.ask_book.limit_orders.len(), 0);
+}
+
+#[test]
+fn test_self_trade_fill_or_kill() {
+	let mut market = Market::new(1);
+	market.self_trade_prevention = Some(SelfTradePrevention::CancelNewest);
+	market.add_order(owned_order(1, 8, "1", "1", OrderSide::Bid)).unwrap();
+	market.add_order(owned_order(2, 7, "1", "1", OrderSide::Bid)).unwrap();
+	market.add_order(owned_order(3, 8, "1", "1", OrderSide::Bid)).unwrap();
+
+	let mut order = owned_order(4, 7, "1", "2", OrderSide::Ask);
+	order.time_in_force = TimeInForce::FillOrKill;
+	let result = start_match(order, &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	assert!(result.self_trades.is_empty());
+	assert_eq!(result.canceled[0].id, 4);
+	assert_eq!(market.bid_book.limit_orders.len(), 3);
+
+	market.self_trade_prevention = Some(SelfTradePrevention::CancelOldest);
+	let mut order = owned_order(5, 7, "1", "2", OrderSide::Ask);
+	order.time_in_force = TimeInForce::FillOrKill;
+	let result = start_match(order, &mut market).unwrap();
+	assert_eq!(result.trades.len(), 2);
+	assert_eq!(result.canceled[0].id, 2);
+	assert_eq!(market.bid_book.limit_orders.len(), 0);
+}
+
+#[test]
+fn test_market_orders_at_reference_price() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "0", "1", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(2, "0", "1", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+
+	let result = start_match(Order::new(3, "0", "1", OrderKind::Market, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	assert_eq!(market.ask_book.market_orders.len(), 1);
+	market.cancel_order(3).unwrap();
+
+	let result = start_match(Order::new(4, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades[0].price, Decimal::new(5, 0));
+
+	market.reference_price = Some(Decimal::new(4, 0));
+	let result = start_match(Order::new(5, "0", "0.5", OrderKind::Market, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(result.trades[0].maker_id, 2);
+	assert_eq!(result.trades[0].price, Decimal::new(4, 0));
+
+	let result = start_match(Order::new(6, "5", "0.5", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	assert!(result.trades.is_empty());
+	assert_eq!(market.ask_book.limit_orders.len(), 1);
+	assert_eq!(market.bid_book.market_orders.len(), 1);
+
+	let result = start_match(Order::new(7, "3", "0.5", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades[0].price, Decimal::new(4, 0));
+	assert_eq!(market.last_price, Some(Decimal::new(4, 0)));
+	assert_eq!(market.bid_book.market_orders.len(), 0);
+}
+
+#[test]
+fn test_market_order_remainder_policy() {
+	let mut market = Market::new(1);
+	market.market_order_remainder = MarketOrderRemainder::ConvertToLimit;
+	let result = start_match(Order::new(2, "0", "1", OrderKind::Market, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	assert_eq!(result.canceled[0].id, 2);
+	assert!(market.bid_book.limit_orders.peek().is_none());
+
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+
+	let result = start_match(Order::new(3, "6", "2", OrderKind::Market, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert!(result.canceled.is_empty());
+	assert_eq!(market.bid_book.market_orders.len(), 0);
+	let order = market.bid_book.limit_orders.peek().unwrap();
+	assert_eq!((order.id, order.kind, order.price), (3, OrderKind::Limit, Decimal::new(5, 0)));
+	assert_eq!(order.volume_remained(), Decimal::new(1, 0));
+
+	market.market_order_remainder = MarketOrderRemainder::Cancel;
+	let result = start_match(Order::new(4, "0", "2", OrderKind::Market, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(result.canceled[0].id, 4);
+	assert_eq!(result.canceled[0].volume_remained(), Decimal::new(1, 0));
+	assert_eq!(market.ask_book.market_orders.len(), 0);
+	assert_eq!(market.ask_book.limit_orders.len(), 0);
+}
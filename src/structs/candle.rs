@@ -0,0 +1,217 @@
+use std::cell::Cell;
+use std::cmp;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use rust_decimal::Decimal;
+use super::*;
+
+/// Source of the current time in milliseconds, injected so bars can be closed deterministically.
+pub trait Clock {
+	fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> u64 {
+		let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+		elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())
+	}
+}
+
+/// A clock that only moves when told to, clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+	time: Rc<Cell<u64>>
+}
+
+impl ManualClock {
+	pub fn new(time: u64) -> ManualClock {
+		ManualClock { time: Rc::new(Cell::new(time)) }
+	}
+
+	pub fn set(&self, time: u64) {
+		self.time.set(time);
+	}
+}
+
+impl Clock for ManualClock {
+	fn now(&self) -> u64 {
+		self.time.get()
+	}
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interval {
+	Minute,
+	FiveMinutes,
+	Hour,
+	Day
+}
+
+impl Interval {
+	pub fn millis(&self) -> u64 {
+		match *self {
+			Interval::Minute => 60_000,
+			Interval::FiveMinutes => 300_000,
+			Interval::Hour => 3_600_000,
+			Interval::Day => 86_400_000
+		}
+	}
+
+	/// Start of the bar `time` falls into.
+	pub fn open_time(&self, time: u64) -> u64 {
+		time - time % self.millis()
+	}
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Candle {
+	pub interval: Interval,
+	pub open_time: u64,
+	pub open: Decimal,
+	pub high: Decimal,
+	pub low: Decimal,
+	pub close: Decimal,
+	pub volume: Decimal
+}
+
+impl Candle {
+	pub fn new(interval: Interval, open_time: u64, trade: &Trade) -> Candle {
+		Candle {
+			interval,
+			open_time,
+			open: trade.price,
+			high: trade.price,
+			low: trade.price,
+			close: trade.price,
+			volume: trade.volume
+		}
+	}
+
+	pub fn add_trade(&mut self, trade: &Trade) {
+		self.high = cmp::max(self.high, trade.price);
+		self.low = cmp::min(self.low, trade.price);
+		self.close = trade.price;
+		self.volume += trade.volume;
+	}
+}
+
+/// Builds OHLCV bars out of trades. Intervals without trades produce no bar.
+pub struct CandleAggregator {
+	pub intervals: Vec<Interval>,
+	clock: Box<dyn Clock>,
+	/// The bar being built for each of `intervals`, if it has seen a trade.
+	current: Vec<Option<Candle>>,
+	/// Finished bars not yet taken by `take_closed`, in closing order.
+	closed: Vec<Candle>
+}
+
+impl CandleAggregator {
+	pub fn new(intervals: Vec<Interval>, clock: Box<dyn Clock>) -> CandleAggregator {
+		let current = intervals.iter().map(|_| None).collect();
+		CandleAggregator { intervals, clock, current, closed: Vec::new() }
+	}
+
+	/// Closes the bars whose interval has ended by now.
+	pub fn tick(&mut self) {
+		let now = self.clock.now();
+		for (interval, current) in self.intervals.iter().zip(self.current.iter_mut()) {
+			if current.as_ref().is_some_and(|candle| candle.open_time != interval.open_time(now)) {
+				self.closed.push(current.take().unwrap());
+			}
+		}
+	}
+
+	/// Adds a trade to the bars of the current time.
+	pub fn record(&mut self, trade: &Trade) {
+		self.tick();
+		let now = self.clock.now();
+		for (interval, current) in self.intervals.iter().zip(self.current.iter_mut()) {
+			match *current {
+				Some(ref mut candle) => candle.add_trade(trade),
+				None => *current = Some(Candle::new(*interval, interval.open_time(now), trade))
+			}
+		}
+	}
+
+	pub fn current(&self, interval: Interval) -> Option<&Candle> {
+		let index = self.intervals.iter().position(|&candidate| candidate == interval)?;
+		self.current[index].as_ref()
+	}
+
+	pub fn take_closed(&mut self) -> Vec<Candle> {
+		self.closed.drain(..).collect()
+	}
+
+	/// Rebuilds the bars of a market by replaying its command log, each command with the time
+	/// it was executed at, as `journal::rebuild_candles` does with the journaled times. Commands
+	/// the market refused are skipped as they were live.
+	pub fn rebuild(market_id: u64, log: &[(u64, Command)], intervals: Vec<Interval>) -> CandleAggregator {
+		let clock = ManualClock::new(0);
+		let mut market = Market::new(market_id);
+		market.candles = Some(CandleAggregator::new(intervals, Box::new(clock.clone())));
+		for &(time, ref command) in log {
+			clock.set(time);
+			let _ = market.execute(command.clone());
+		}
+		market.candles.take().unwrap()
+	}
+}
+
+#[cfg(test)]
+fn trade_at(price: i64, volume: i64) -> Trade {
+	let taker = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let maker = Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	Trade::new(1, 1, &taker, &maker, Decimal::new(price, 0), Decimal::new(volume, 0))
+}
+
+#[test]
+fn test_candles() {
+	let clock = ManualClock::new(90_000);
+	let mut candles = CandleAggregator::new(vec![Interval::Minute, Interval::FiveMinutes], Box::new(clock.clone()));
+	candles.record(&trade_at(5, 1));
+	candles.record(&trade_at(7, 2));
+	candles.record(&trade_at(4, 1));
+	clock.set(119_999);
+	candles.record(&trade_at(6, 1));
+	assert_eq!(candles.current(Interval::Minute), Some(&Candle {
+		interval: Interval::Minute,
+		open_time: 60_000,
+		open: Decimal::new(5, 0),
+		high: Decimal::new(7, 0),
+		low: Decimal::new(4, 0),
+		close: Decimal::new(6, 0),
+		volume: Decimal::new(5, 0)
+	}));
+	assert!(candles.current(Interval::Hour).is_none());
+
+	clock.set(120_000);
+	candles.tick();
+	let closed = candles.take_closed();
+	assert_eq!(closed.len(), 1);
+	assert_eq!(closed[0].interval, Interval::Minute);
+	assert!(candles.current(Interval::Minute).is_none());
+
+	candles.record(&trade_at(8, 1));
+	assert_eq!(candles.current(Interval::Minute).unwrap().open, Decimal::new(8, 0));
+	assert_eq!(candles.current(Interval::FiveMinutes).unwrap().volume, Decimal::new(6, 0));
+}
+
+#[test]
+fn test_rebuild_candles() {
+	let log = vec![
+		(1_000, Command::Submit(Order::new(1, "5", "2", OrderKind::Limit, OrderSide::Ask).unwrap())),
+		(2_000, Command::Submit(Order::new(2, "5", "1", OrderKind::Limit, OrderSide::Bid).unwrap())),
+		(3_000, Command::Submit(Order::new(3, "4", "1", OrderKind::Limit, OrderSide::Ask).unwrap())),
+		(61_000, Command::Cancel(1)),
+		(62_000, Command::Submit(Order::new(4, "4", "3", OrderKind::Limit, OrderSide::Bid).unwrap()))
+	];
+	let mut candles = CandleAggregator::rebuild(1, &log, vec![Interval::Minute]);
+	let closed = candles.take_closed();
+	assert_eq!(closed.len(), 1);
+	assert_eq!((closed[0].open_time, closed[0].open, closed[0].volume), (0, Decimal::new(5, 0), Decimal::new(1, 0)));
+	let current = candles.current(Interval::Minute).unwrap();
+	assert_eq!((current.open_time, current.close, current.volume), (60_000, Decimal::new(4, 0), Decimal::new(1, 0)));
+}
@@ -0,0 +1,95 @@
+use rust_decimal::Decimal;
+use super::*;
+
+/// A request that changes a market, the unit in which order flow is logged and replayed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+	Submit(Order),
+	Cancel(u64),
+	Amend {
+		id: u64,
+		price: Option<Decimal>,
+		volume: Option<Decimal>
+	},
+	/// Settings are logged like order flow, so a replay matches the way the market did.
+	Configure(MarketConfig)
+}
+
+/// A line of an order log, `{"action":"submit"|"cancel","order":{...}}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Request {
+	Submit {
+		order: Order
+	},
+	Cancel {
+		order: OrderId
+	}
+}
+
+/// The part of an order a cancel request needs.
+#[derive(Debug, Deserialize)]
+pub struct OrderId {
+	pub id: u64
+}
+
+impl From<Request> for Command {
+	fn from(request: Request) -> Command {
+		match request {
+			Request::Submit { order } => Command::Submit(order),
+			Request::Cancel { order } => Command::Cancel(order.id)
+		}
+	}
+}
+
+#[test]
+fn test_request() {
+	let request: Request = serde_json::from_str(r#"{"action":"submit","order":{"id":1,"price":"2","volume":"1","type":"bid"}}"#).unwrap();
+	match Command::from(request) {
+		Command::Submit(order) => assert_eq!((order.id, order.side), (1, OrderSide::Bid)),
+		command => panic!("unexpected {:?}", command)
+	}
+	let request: Request = serde_json::from_str(r#"{"action":"cancel","order":{"id":1,"type":"bid"}}"#).unwrap();
+	match Command::from(request) {
+		Command::Cancel(id) => assert_eq!(id, 1),
+		command => panic!("unexpected {:?}", command)
+	}
+	assert!(serde_json::from_str::<Request>(r#"{"action":"amend","order":{"id":1}}"#).is_err());
+}
+
+#[test]
+fn test_submit_ignores_engine_fields() {
+	let mut market = Market::new(1);
+	let lines = [
+		r#"{"action":"submit","order":{"id":1,"price":"5","volume":"10","type":"ask","display_volume":"1","displayed":"100","sequence":9}}"#,
+		r#"{"action":"submit","order":{"id":2,"price":"6","volume":"10","type":"ask","filled":"9.5"}}"#
+	];
+	for line in lines.iter() {
+		market.execute(Command::from(serde_json::from_str::<Request>(line).unwrap())).unwrap();
+	}
+	let asks = market.depth(2).asks;
+	assert_eq!((asks[0].volume, asks[1].volume), (Decimal::new(1, 0), Decimal::new(10, 0)));
+	assert_eq!(market.ask_book.limit_orders.get(1).unwrap().sequence, 1);
+
+	let bid = Order::new(3, "5", "20", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let result = market.execute(Command::Submit(bid)).unwrap();
+	assert_eq!(result.trades.iter().fold(Decimal::new(0, 0), |sum, trade| sum + trade.volume), Decimal::new(10, 0));
+	assert!(market.ask_book.limit_orders.get(1).is_none());
+	assert_eq!(market.depth(2).asks[0].price, Decimal::new(6, 0));
+}
+
+#[test]
+fn test_execute_commands() {
+	let mut market = Market::new(1);
+	let order = Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	market.execute(Command::Submit(order.clone())).unwrap();
+	assert_eq!(market.execute(Command::Submit(order)).unwrap_err(), MatchingError::DuplicateOrder(1));
+
+	market.execute(Command::Amend { id: 1, price: Some(Decimal::new(3, 0)), volume: None }).unwrap();
+	assert_eq!(market.bbo.ask, Some(Decimal::new(3, 0)));
+
+	let result = market.execute(Command::Cancel(1)).unwrap();
+	assert_eq!(result.canceled[0].id, 1);
+	assert_eq!(market.execute(Command::Cancel(1)).unwrap_err(), MatchingError::UnknownOrder(1));
+}
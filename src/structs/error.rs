@@ -0,0 +1,47 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchingError {
+	/// A price or volume string could not be parsed as a decimal.
+	InvalidDecimal(String),
+	/// The order's price is not positive, or negative for a market order.
+	InvalidPrice(u64),
+	/// The order's volume or iceberg display volume is not positive.
+	InvalidVolume(u64),
+	/// A stop order was given no positive trigger price.
+	InvalidTriggerPrice(u64),
+	/// An order was handed to the book of the other side.
+	SideMismatch(u64),
+	/// An order was handed to a book that does not hold its kind.
+	KindMismatch(u64),
+	/// An order with the same id is resting or waiting for its trigger, ids of orders that
+	/// have left the market can be used again.
+	DuplicateOrder(u64),
+	/// No order with this id is in the market.
+	UnknownOrder(u64)
+}
+
+impl fmt::Display for MatchingError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			MatchingError::InvalidDecimal(ref value) => write!(f, "invalid decimal {:?}", value),
+			MatchingError::InvalidPrice(id) => write!(f, "invalid price for order {}", id),
+			MatchingError::InvalidVolume(id) => write!(f, "invalid volume for order {}", id),
+			MatchingError::InvalidTriggerPrice(id) => write!(f, "invalid trigger price for order {}", id),
+			MatchingError::SideMismatch(id) => write!(f, "order {} is on the other side of the book", id),
+			MatchingError::KindMismatch(id) => write!(f, "order {} can not be held by this book", id),
+			MatchingError::DuplicateOrder(id) => write!(f, "order {} already exists", id),
+			MatchingError::UnknownOrder(id) => write!(f, "unknown order {}", id)
+		}
+	}
+}
+
+impl Error for MatchingError {}
+
+#[test]
+fn test_display() {
+	assert_eq!(MatchingError::UnknownOrder(3).to_string(), "unknown order 3");
+	assert_eq!(MatchingError::InvalidDecimal("x".to_string()).to_string(), "invalid decimal \"x\"");
+}
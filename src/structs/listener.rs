@@ -0,0 +1,144 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use rust_decimal::Decimal;
+use super::*;
+use matching::{RejectReason, SelfTradePrevented};
+#[cfg(test)]
+use matching;
+
+/// Receives what happens in a `Market` as it happens. Every callback does nothing by default.
+pub trait MatchingListener {
+	/// `order` passed validation and got its arrival sequence, a post-only order may still be rejected after.
+	fn on_accepted(&mut self, _order: &Order) {}
+	fn on_rejected(&mut self, _order: &Order, _reason: &RejectReason) {}
+	/// One side of `trade` filled `order_id`, which is completely filled once `remaining` is zero.
+	fn on_fill(&mut self, _order_id: u64, _trade: &Trade, _remaining: Decimal) {}
+	fn on_canceled(&mut self, _order: &Order) {}
+	fn on_trade(&mut self, _trade: &Trade) {}
+	/// Two orders of the same owner met and were kept from trading as the market's mode says.
+	fn on_self_trade_prevented(&mut self, _prevented: &SelfTradePrevented) {}
+	fn on_level_change(&mut self, _update: &LevelUpdate) {}
+	/// The best price of either book moved, called after the level changes that caused it.
+	fn on_bbo_change(&mut self, _bbo: &Bbo) {}
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+	Accepted(u64),
+	Rejected(u64, RejectReason),
+	Filled {
+		order_id: u64,
+		trade_sequence: u64,
+		remaining: Decimal
+	},
+	Canceled(u64),
+	Trade(Trade),
+	SelfTradePrevented(SelfTradePrevented),
+	LevelChange(LevelUpdate),
+	BboChange(Bbo)
+}
+
+/// Keeps every callback as an `Event`, clones share the same record.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingListener {
+	pub events: Rc<RefCell<Vec<Event>>>
+}
+
+impl RecordingListener {
+	pub fn new() -> RecordingListener {
+		RecordingListener::default()
+	}
+
+	pub fn take_events(&self) -> Vec<Event> {
+		self.events.borrow_mut().drain(..).collect()
+	}
+}
+
+impl MatchingListener for RecordingListener {
+	fn on_accepted(&mut self, order: &Order) {
+		self.events.borrow_mut().push(Event::Accepted(order.id));
+	}
+
+	fn on_rejected(&mut self, order: &Order, reason: &RejectReason) {
+		self.events.borrow_mut().push(Event::Rejected(order.id, reason.clone()));
+	}
+
+	fn on_fill(&mut self, order_id: u64, trade: &Trade, remaining: Decimal) {
+		self.events.borrow_mut().push(Event::Filled { order_id, trade_sequence: trade.sequence, remaining });
+	}
+
+	fn on_canceled(&mut self, order: &Order) {
+		self.events.borrow_mut().push(Event::Canceled(order.id));
+	}
+
+	fn on_trade(&mut self, trade: &Trade) {
+		self.events.borrow_mut().push(Event::Trade(trade.clone()));
+	}
+
+	fn on_self_trade_prevented(&mut self, prevented: &SelfTradePrevented) {
+		self.events.borrow_mut().push(Event::SelfTradePrevented(prevented.clone()));
+	}
+
+	fn on_level_change(&mut self, update: &LevelUpdate) {
+		self.events.borrow_mut().push(Event::LevelChange(update.clone()));
+	}
+
+	fn on_bbo_change(&mut self, bbo: &Bbo) {
+		self.events.borrow_mut().push(Event::BboChange(*bbo));
+	}
+}
+
+#[test]
+fn test_listeners() {
+	let mut market = Market::new(1);
+	let first = RecordingListener::new();
+	let second = RecordingListener::new();
+	market.add_listener(Box::new(first.clone()));
+	market.add_listener(Box::new(second.clone()));
+
+	market.add_order(Order::new(1, "5", "2", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	assert_eq!(first.take_events()[0], Event::Accepted(1));
+
+	let result = matching::start_match(Order::new(2, "5", "1", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	let events = first.take_events();
+	assert_eq!(events, vec![
+		Event::Accepted(2),
+		Event::Trade(result.trades[0].clone()),
+		Event::Filled { order_id: 1, trade_sequence: 1, remaining: Decimal::new(1, 0) },
+		Event::Filled { order_id: 2, trade_sequence: 1, remaining: Decimal::new(0, 0) },
+		Event::LevelChange(LevelUpdate { sequence: 2, side: OrderSide::Ask, price: Decimal::new(5, 0), volume: Decimal::new(1, 0) })
+	]);
+
+	let mut order = Order::new(3, "5", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	order.post_only = true;
+	matching::start_match(order, &mut market).unwrap();
+	let order = Order::new(4, "5", "0", OrderKind::Limit, OrderSide::Bid).unwrap();
+	assert!(matching::start_match(order, &mut market).is_err());
+	market.cancel_order(1).unwrap();
+	let events = first.take_events();
+	assert_eq!(events[1], Event::Rejected(3, RejectReason::PostOnlyWouldTake));
+	assert_eq!(events[2], Event::Rejected(4, RejectReason::Invalid(MatchingError::InvalidVolume(4))));
+	assert_eq!(events[3], Event::Canceled(1));
+	assert_eq!(events[5], Event::BboChange(Bbo { bid: None, ask: None }));
+	assert_eq!(second.take_events().len(), 14);
+}
+
+#[test]
+fn test_self_trade_events() {
+	let mut market = Market::new(1);
+	market.self_trade_prevention = Some(SelfTradePrevention::CancelOldest);
+	let listener = RecordingListener::new();
+	market.add_listener(Box::new(listener.clone()));
+	let mut ask = Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	ask.owner = Some(7);
+	market.add_order(ask).unwrap();
+	listener.take_events();
+
+	let mut bid = Order::new(2, "5", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	bid.owner = Some(7);
+	let result = matching::start_match(bid, &mut market).unwrap();
+	let events = listener.take_events();
+	assert_eq!(events[1], Event::SelfTradePrevented(result.self_trades[0].clone()));
+	assert_eq!(events[2], Event::Canceled(1));
+}
@@ -1,33 +1,483 @@
 
 use super::*;
+use std::cmp;
+use std::collections::HashMap;
+use rust_decimal::Decimal;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error;
+use matching::{self, MatchResult, RejectReason};
+use ds::{Queue, List};
 
-pub struct Market {
+/// What happens when an order would trade with a resting order of the same owner.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTradePrevention {
+	/// Cancel the rest of the incoming order.
+	CancelNewest,
+	/// Cancel the resting order and keep matching.
+	CancelOldest,
+	/// Cancel both orders.
+	CancelBoth,
+	/// Take the smaller remaining volume off both orders and cancel whichever is left empty.
+	DecrementAndCancel
+}
+
+/// What happens to the part of a market order that found nothing to trade with.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketOrderRemainder {
+	/// Cancel it.
+	Cancel,
+	/// Turn it into a limit order at the last traded price, canceling it if nothing traded yet.
+	ConvertToLimit,
+	/// Keep it in the book's market orders until a counter order arrives.
+	Rest
+}
+
+/// The settings of a market that change how orders match.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MarketConfig {
+	pub post_only_tick: Option<Decimal>,
+	pub self_trade_prevention: Option<SelfTradePrevention>,
+	pub reference_price: Option<Decimal>,
+	pub market_order_remainder: MarketOrderRemainder
+}
+
+/// Aggregated top of both books, as returned by `Market::depth`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Depth {
+	/// Sequence of the last `LevelUpdate` the levels include, later updates apply on top of them.
+	pub level_sequence: u64,
+	pub asks: Vec<PriceLevel>,
+	pub bids: Vec<PriceLevel>
+}
+
+/// New aggregate volume of a price level, zero once the level is gone.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct LevelUpdate {
+	/// Per market and gapless, a client missing one has to resynchronize from `Market::depth`
+	/// and drop the updates its `level_sequence` already covers.
+	pub sequence: u64,
+	pub side: OrderSide,
+	pub price: Decimal,
+	pub volume: Decimal
+}
+
+/// Best bid and offer of the limit books.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Bbo {
+	pub bid: Option<Decimal>,
+	pub ask: Option<Decimal>
+}
+
+impl Bbo {
+	pub fn spread(&self) -> Option<Decimal> {
+		Some(self.ask? - self.bid?)
+	}
+
+	pub fn mid(&self) -> Option<Decimal> {
+		Some((self.ask? + self.bid?) / Decimal::new(2, 0))
+	}
+}
+
+/// Both books of one instrument, their limit orders kept in `L` and market orders in `M`.
+pub struct Market<L = LimitOrders, M = List<Order>> {
 	pub id: u64,
-	pub ask_book: OrderBook,
-	pub bid_book: OrderBook
+	pub ask_book: OrderBook<L, M>,
+	pub bid_book: OrderBook<L, M>,
+	pub ask_triggers: TriggerBook,
+	pub bid_triggers: TriggerBook,
+	pub last_price: Option<Decimal>,
+	pub trade_sequence: u64,
+	pub order_sequence: u64,
+	/// Side and kind of every resting order by id, which together locate its container.
+	pub order_index: HashMap<u64, (OrderSide, OrderKind)>,
+	/// When set, crossing post-only orders are re-priced one tick behind the best counter price instead of rejected.
+	pub post_only_tick: Option<Decimal>,
+	/// Orders of the same owner trade with each other when unset.
+	pub self_trade_prevention: Option<SelfTradePrevention>,
+	/// Price for trades against resting market orders. Without it they trade at the incoming
+	/// order's limit, and market orders never trade with each other.
+	pub reference_price: Option<Decimal>,
+	pub market_order_remainder: MarketOrderRemainder,
+	pub level_sequence: u64,
+	/// Level changes not yet taken by `take_level_updates`, only kept when set.
+	/// Listeners get them either way.
+	pub level_updates: Option<Vec<LevelUpdate>>,
+	pub bbo: Bbo,
+	/// Every new top of book not yet taken by `take_bbo_changes`, only kept when set.
+	/// Listeners get them either way.
+	pub bbo_changes: Option<Vec<Bbo>>,
+	/// Fed with every trade of the market when set.
+	pub candles: Option<CandleAggregator>,
+	/// Called back in the order they were added.
+	pub listeners: Vec<Box<dyn MatchingListener>>
 }
 
 impl Market {
 	pub fn new(id: u64) -> Market {
+		Market::with_containers(id)
+	}
+}
+
+impl<L: Queue<Order>, M: Queue<Order>> Market<L, M> {
+	pub fn with_containers(id: u64) -> Market<L, M> {
 		Market {
-			id: id,
-			ask_book: OrderBook::new(OrderSide::Ask),
-			bid_book: OrderBook::new(OrderSide::Bid)
+			id,
+			ask_book: OrderBook::with_containers(OrderSide::Ask),
+			bid_book: OrderBook::with_containers(OrderSide::Bid),
+			ask_triggers: TriggerBook::new(OrderSide::Ask),
+			bid_triggers: TriggerBook::new(OrderSide::Bid),
+			last_price: None,
+			trade_sequence: 0,
+			order_sequence: 0,
+			order_index: HashMap::new(),
+			post_only_tick: None,
+			self_trade_prevention: None,
+			reference_price: None,
+			market_order_remainder: MarketOrderRemainder::Rest,
+			level_sequence: 0,
+			level_updates: None,
+			bbo: Bbo { bid: None, ask: None },
+			bbo_changes: None,
+			candles: None,
+			listeners: Vec::new()
+		}
+	}
+
+	pub fn next_sequence(&mut self) -> u64 {
+		self.order_sequence += 1;
+		self.order_sequence
+	}
+
+	/// Checks that `order` is valid and that its id is not taken by a resting or untriggered order.
+	/// Ids of filled and canceled orders are free again, keeping them unique for good is up to the caller.
+	pub fn check_order(&self, order: &Order) -> Result<(), MatchingError> {
+		order.validate()?;
+		if self.order_index.contains_key(&order.id) {
+			return Err(MatchingError::DuplicateOrder(order.id))
+		}
+		Ok(())
+	}
+
+	/// Rests `order` in its book without matching it.
+	pub fn add_order(&mut self, mut order: Order) -> Result<(), MatchingError> {
+		order.reset_progress();
+		if let Err(error) = self.check_order(&order) {
+			self.notify(|listener| listener.on_rejected(&order, &RejectReason::Invalid(error.clone())));
+			return Err(error)
+		}
+		order.sequence = self.next_sequence();
+		self.notify(|listener| listener.on_accepted(&order));
+		self.rest_order(order)?;
+		self.publish_levels();
+		Ok(())
+	}
+
+	/// Places an order that already carries its arrival sequence.
+	pub fn rest_order(&mut self, order: Order) -> Result<(), MatchingError> {
+		let (id, location) = (order.id, (order.side, order.kind));
+		match (order.kind, order.side) {
+			(OrderKind::Stop, OrderSide::Ask) | (OrderKind::StopLimit, OrderSide::Ask) => self.ask_triggers.add_order(order),
+			(OrderKind::Stop, OrderSide::Bid) | (OrderKind::StopLimit, OrderSide::Bid) => self.bid_triggers.add_order(order),
+			(_, OrderSide::Ask) => self.ask_book.add_order(order),
+			(_, OrderSide::Bid) => self.bid_book.add_order(order)
+		}?;
+		self.order_index.insert(id, location);
+		Ok(())
+	}
+
+	/// Removes a resting or untriggered order, returning it with what it has filled so far.
+	pub fn cancel_order(&mut self, id: u64) -> Result<Order, MatchingError> {
+		let (side, kind) = self.order_index.remove(&id).ok_or(MatchingError::UnknownOrder(id))?;
+		let order = match (kind, side) {
+			(OrderKind::Stop, OrderSide::Ask) | (OrderKind::StopLimit, OrderSide::Ask) => self.ask_triggers.cancel_order(id),
+			(OrderKind::Stop, OrderSide::Bid) | (OrderKind::StopLimit, OrderSide::Bid) => self.bid_triggers.cancel_order(id),
+			(_, OrderSide::Ask) => self.ask_book.cancel_order(kind, id),
+			(_, OrderSide::Bid) => self.bid_book.cancel_order(kind, id)
+		}.ok_or(MatchingError::UnknownOrder(id))?;
+		self.notify(|listener| listener.on_canceled(&order));
+		self.publish_levels();
+		Ok(order)
+	}
+
+	pub fn get_order_mut(&mut self, id: u64) -> Option<&mut Order> {
+		let (side, kind) = *self.order_index.get(&id)?;
+		match (kind, side) {
+			(OrderKind::Stop, OrderSide::Ask) | (OrderKind::StopLimit, OrderSide::Ask) => self.ask_triggers.get_mut(id),
+			(OrderKind::Stop, OrderSide::Bid) | (OrderKind::StopLimit, OrderSide::Bid) => self.bid_triggers.get_mut(id),
+			(_, OrderSide::Ask) => self.ask_book.get_order_mut(kind, id),
+			(_, OrderSide::Bid) => self.bid_book.get_order_mut(kind, id)
+		}
+	}
+
+	/// Changes the price and/or total volume of a resting order. Reducing the volume keeps
+	/// the order's time priority, any price change or size increase re-enters it as a new
+	/// order, matching it if the new price crosses. Amending the volume down to what has
+	/// already been filled cancels the order. An amendment that is refused, such as a post-only
+	/// order now crossing, leaves the order resting as it was.
+	pub fn amend_order(&mut self, id: u64, price: Option<Decimal>, volume: Option<Decimal>) -> Result<MatchResult, MatchingError> {
+		let zero = Decimal::new(0, 0);
+		if price.is_some_and(|price| price <= zero) {
+			return Err(MatchingError::InvalidPrice(id))
+		}
+		if volume.is_some_and(|volume| volume <= zero) {
+			return Err(MatchingError::InvalidVolume(id))
+		}
+		let reduced = {
+			let order = self.get_order_mut(id).ok_or(MatchingError::UnknownOrder(id))?;
+			let volume = volume.unwrap_or(order.volume);
+			if price.is_none_or(|price| price == order.price) && volume <= order.volume && volume > order.filled {
+				let displayed = order.volume_displayed();
+				order.volume = volume;
+				order.displayed = cmp::min(order.displayed, order.volume_remained());
+				Some((order.side, order.kind, order.price, displayed - order.volume_displayed()))
+			} else {
+				None
+			}
+		};
+		if let Some((side, kind, price, reduced)) = reduced {
+			match (kind, side) {
+				(OrderKind::Limit, OrderSide::Ask) => self.ask_book.reduce_level(price, reduced),
+				(OrderKind::Limit, OrderSide::Bid) => self.bid_book.reduce_level(price, reduced),
+				_ => ()
+			}
+			self.publish_levels();
+			return Ok(MatchResult::default())
+		}
+
+		let mut order = self.get_order_mut(id).ok_or(MatchingError::UnknownOrder(id))?.clone();
+		if let Some(price) = price {
+			order.price = price;
+		}
+		if let Some(volume) = volume {
+			order.volume = volume;
+		}
+		// checked while the original still rests, re-entering can not fail once it is canceled
+		if order.volume > order.filled {
+			order.validate()?;
+			if order.post_only && !matching::reprice_post_only(&mut order, self) {
+				let mut result = MatchResult::default();
+				result.rejected.push((order, RejectReason::PostOnlyWouldTake));
+				self.notify_result(&result);
+				return Ok(result)
+			}
+		}
+		self.cancel_order(id)?;
+		if order.volume <= order.filled {
+			let mut result = MatchResult::default();
+			result.canceled.push(order);
+			return Ok(result)
+		}
+		order.displayed = Decimal::new(0, 0);
+		matching::match_order(order, self)
+	}
+
+	pub fn config(&self) -> MarketConfig {
+		MarketConfig {
+			post_only_tick: self.post_only_tick,
+			self_trade_prevention: self.self_trade_prevention,
+			reference_price: self.reference_price,
+			market_order_remainder: self.market_order_remainder
+		}
+	}
+
+	/// Changes the settings of the market, resting orders stay as they are.
+	pub fn configure(&mut self, config: MarketConfig) {
+		self.post_only_tick = config.post_only_tick;
+		self.self_trade_prevention = config.self_trade_prevention;
+		self.reference_price = config.reference_price;
+		self.market_order_remainder = config.market_order_remainder;
+	}
+
+	/// Applies `command` as `matching::start_match`, `cancel_order`, `amend_order` or `configure` would.
+	pub fn execute(&mut self, command: Command) -> Result<MatchResult, MatchingError> {
+		match command {
+			Command::Submit(order) => matching::start_match(order, self),
+			Command::Cancel(id) => {
+				let mut result = MatchResult::default();
+				result.canceled.push(self.cancel_order(id)?);
+				Ok(result)
+			},
+			Command::Amend { id, price, volume } => self.amend_order(id, price, volume),
+			Command::Configure(config) => {
+				self.configure(config);
+				Ok(MatchResult::default())
+			}
+		}
+	}
+
+	pub fn add_listener(&mut self, listener: Box<dyn MatchingListener>) {
+		self.listeners.push(listener);
+	}
+
+	pub fn notify<F: FnMut(&mut dyn MatchingListener)>(&mut self, mut callback: F) {
+		for listener in self.listeners.iter_mut() {
+			callback(listener.as_mut());
+		}
+	}
+
+	/// Reports the trades, prevented self-trades, cancels and rejections of a match to the listeners.
+	pub fn notify_result(&mut self, result: &MatchResult) {
+		if self.listeners.is_empty() {
+			return
+		}
+		for trade in result.trades.iter() {
+			self.notify(|listener| {
+				listener.on_trade(trade);
+				listener.on_fill(trade.maker_id, trade, trade.maker_remaining);
+				listener.on_fill(trade.taker_id, trade, trade.taker_remaining);
+			});
+		}
+		for prevented in result.self_trades.iter() {
+			self.notify(|listener| listener.on_self_trade_prevented(prevented));
+		}
+		for order in result.canceled.iter() {
+			self.notify(|listener| listener.on_canceled(order));
+		}
+		for (order, reason) in result.rejected.iter() {
+			self.notify(|listener| listener.on_rejected(order, reason));
 		}
 	}
 
-	pub fn add_order(&mut self, order: Order) {
-		match order.side {
-			OrderSide::Ask => self.ask_book.add_order(order),
-			OrderSide::Bid => self.bid_book.add_order(order)
+	/// Turns the levels changed since the last call into sequenced `LevelUpdate`s and
+	/// refreshes the cached `bbo`.
+	pub fn publish_levels(&mut self) {
+		for &side in [OrderSide::Ask, OrderSide::Bid].iter() {
+			let order_book = match side {
+				OrderSide::Ask => &mut self.ask_book,
+				OrderSide::Bid => &mut self.bid_book
+			};
+			for price in order_book.take_changed_levels() {
+				let volume = order_book.levels.get(&price).map_or(Decimal::new(0, 0), |level| level.volume);
+				self.level_sequence += 1;
+				let update = LevelUpdate { sequence: self.level_sequence, side, price, volume };
+				for listener in self.listeners.iter_mut() {
+					listener.on_level_change(&update);
+				}
+				if let Some(updates) = self.level_updates.as_mut() {
+					updates.push(update);
+				}
+			}
+		}
+		let bbo = Bbo {
+			bid: self.bid_book.levels.keys().next_back().cloned(),
+			ask: self.ask_book.levels.keys().next().cloned()
+		};
+		if bbo != self.bbo {
+			self.bbo = bbo;
+			for listener in self.listeners.iter_mut() {
+				listener.on_bbo_change(&bbo);
+			}
+			if let Some(changes) = self.bbo_changes.as_mut() {
+				changes.push(bbo);
+			}
 		}
 	}
 
-	pub fn cancel_order(&mut self, order: Order) {
-		match order.side {
-			OrderSide::Ask => self.ask_book.cancel_order(order),
-			OrderSide::Bid => self.bid_book.cancel_order(order)
+	/// Drains the top of book changes published so far, oldest first. Always empty unless
+	/// `bbo_changes` is set.
+	pub fn take_bbo_changes(&mut self) -> Vec<Bbo> {
+		self.bbo_changes.as_mut().map_or_else(Vec::new, std::mem::take)
+	}
+
+	/// Drains the level updates published so far, oldest first. Always empty unless
+	/// `level_updates` is set.
+	pub fn take_level_updates(&mut self) -> Vec<LevelUpdate> {
+		self.level_updates.as_mut().map_or_else(Vec::new, std::mem::take)
+	}
+
+	/// Takes the next stop order released by the last trade price, if any.
+	pub fn pop_triggered(&mut self) -> Option<Order> {
+		let last_price = self.last_price?;
+		let order = self.bid_triggers.pop_triggered(last_price)
+			.or_else(|| self.ask_triggers.pop_triggered(last_price));
+		if let Some(ref order) = order {
+			self.order_index.remove(&order.id);
 		}
+		order
+	}
+
+	/// The best `n` limit price levels on each side, the whole book with `usize::MAX`.
+	pub fn depth(&self, n: usize) -> Depth {
+		Depth {
+			level_sequence: self.level_sequence,
+			asks: self.ask_book.depth(n),
+			bids: self.bid_book.depth(n)
+		}
+	}
+
+	pub fn snapshot(&self) -> MarketSnapshot {
+		MarketSnapshot {
+			id: self.id,
+			order_sequence: self.order_sequence,
+			level_sequence: self.level_sequence,
+			asks: self.ask_book.snapshot(),
+			bids: self.bid_book.snapshot()
+		}
+	}
+
+	/// Builds a market holding exactly the resting orders of `snapshot`, with their sequences kept.
+	pub fn from_snapshot(snapshot: &MarketSnapshot) -> Result<Market<L, M>, MatchingError> {
+		let mut market = Market::with_containers(snapshot.id);
+		for &(side, book) in [(OrderSide::Ask, &snapshot.asks), (OrderSide::Bid, &snapshot.bids)].iter() {
+			let orders = book.limit_orders.iter().map(|entry| entry.to_order(OrderKind::Limit, side))
+				.chain(book.market_orders.iter().map(|entry| entry.to_order(OrderKind::Market, side)));
+			for order in orders {
+				market.check_order(&order)?;
+				market.rest_order(order)?;
+			}
+		}
+		market.order_sequence = snapshot.order_sequence;
+		// a client starting from the snapshot has these levels already
+		market.publish_levels();
+		market.take_level_updates();
+		market.level_sequence = snapshot.level_sequence;
+		market.take_bbo_changes();
+		Ok(market)
+	}
+
+	pub fn state(&self) -> MarketState {
+		let orders = self.ask_book.limit_orders.ordered()
+			.chain(self.ask_book.market_orders.ordered())
+			.chain(self.bid_book.limit_orders.ordered())
+			.chain(self.bid_book.market_orders.ordered())
+			.chain(self.ask_triggers.iter())
+			.chain(self.bid_triggers.iter())
+			.cloned()
+			.collect();
+		MarketState {
+			id: self.id,
+			last_price: self.last_price,
+			trade_sequence: self.trade_sequence,
+			order_sequence: self.order_sequence,
+			level_sequence: self.level_sequence,
+			post_only_tick: self.post_only_tick,
+			self_trade_prevention: self.self_trade_prevention,
+			reference_price: self.reference_price,
+			market_order_remainder: self.market_order_remainder,
+			orders
+		}
+	}
+
+	pub fn from_state(state: MarketState) -> Result<Market<L, M>, MatchingError> {
+		let mut market = Market::with_containers(state.id);
+		market.post_only_tick = state.post_only_tick;
+		market.self_trade_prevention = state.self_trade_prevention;
+		market.reference_price = state.reference_price;
+		market.market_order_remainder = state.market_order_remainder;
+		OrderBook::<L, M>::restore(state.orders, |order| {
+			market.check_order(&order)?;
+			market.rest_order(order)
+		})?;
+		market.publish_levels();
+		market.take_level_updates();
+		market.take_bbo_changes();
+		market.last_price = state.last_price;
+		market.trade_sequence = state.trade_sequence;
+		market.order_sequence = state.order_sequence;
+		market.level_sequence = state.level_sequence;
+		Ok(market)
 	}
 
 	//fn match_order(&mut self, order: Order) {
@@ -43,8 +493,322 @@ impl Market {
 
 }
 
+/// Written as its `MarketState`.
+impl<L: Queue<Order>, M: Queue<Order>> Serialize for Market<L, M> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.state().serialize(serializer)
+	}
+}
+
+impl<'de, L: Queue<Order>, M: Queue<Order>> Deserialize<'de> for Market<L, M> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Market<L, M>, D::Error> {
+		Market::from_state(MarketState::deserialize(deserializer)?).map_err(D::Error::custom)
+	}
+}
+
 #[test]
+#[allow(unused_variables, clippy::assertions_on_constants)]
 fn test_ok() {
 	let market = Market::new(1);
 	assert!(true);
 }
+
+#[test]
+fn test_amend_reduce_keeps_priority() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "1", "3", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(2, "1", "3", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+
+	let result = market.amend_order(1, None, Some(Decimal::new(2, 0))).unwrap();
+	assert!(result.trades.is_empty());
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 1);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().volume, Decimal::new(2, 0));
+
+	let result = market.amend_order(1, Some(Decimal::new(1, 0)), Some(Decimal::new(1, 0))).unwrap();
+	assert!(result.canceled.is_empty());
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 1);
+
+	assert_eq!(market.amend_order(3, None, Some(Decimal::new(1, 0))).unwrap_err(), MatchingError::UnknownOrder(3));
+}
+
+#[test]
+fn test_amend_loses_priority() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+
+	market.amend_order(1, None, Some(Decimal::new(2, 0))).unwrap();
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 2);
+	assert_eq!(market.bid_book.limit_orders.len(), 2);
+
+	market.amend_order(1, Some(Decimal::new(2, 0)), None).unwrap();
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 1);
+
+	market.amend_order(1, Some(Decimal::new(1, 0)), None).unwrap();
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().id, 2);
+}
+
+#[test]
+fn test_amend_refused_keeps_order() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	let mut order = Order::new(2, "3", "2", OrderKind::Limit, OrderSide::Bid).unwrap();
+	order.post_only = true;
+	market.add_order(order).unwrap();
+
+	let result = market.amend_order(2, Some(Decimal::new(6, 0)), None).unwrap();
+	assert_eq!(result.rejected[0].1, RejectReason::PostOnlyWouldTake);
+	assert!(result.trades.is_empty());
+	let resting = market.bid_book.limit_orders.get(2).unwrap();
+	assert_eq!((resting.price, resting.volume, resting.sequence), (Decimal::new(3, 0), Decimal::new(2, 0), 2));
+	assert_eq!(market.depth(1).bids[0].volume, Decimal::new(2, 0));
+
+	market.amend_order(2, Some(Decimal::new(4, 0)), None).unwrap();
+	assert_eq!(market.bid_book.limit_orders.get(2).unwrap().price, Decimal::new(4, 0));
+}
+
+#[test]
+fn test_amend_crossing_price_matches() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(2, "1", "3", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+
+	let result = market.amend_order(2, Some(Decimal::new(2, 0)), None).unwrap();
+	assert_eq!(result.trades.len(), 1);
+	assert_eq!(result.trades[0].taker_id, 2);
+	assert_eq!(result.trades[0].price, Decimal::new(2, 0));
+	assert_eq!(market.ask_book.limit_orders.len(), 0);
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().volume_remained(), Decimal::new(2, 0));
+
+	let result = market.amend_order(2, None, Some(Decimal::new(1, 0))).unwrap();
+	assert_eq!(result.canceled[0].id, 2);
+	assert_eq!(market.bid_book.limit_orders.len(), 0);
+}
+
+#[test]
+fn test_cancel_order_by_id() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(2, "1", "1", OrderKind::Market, OrderSide::Ask).unwrap()).unwrap();
+	let mut order = Order::new(3, "1", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+	order.trigger_price = Some(Decimal::new(5, 1));
+	market.add_order(order).unwrap();
+	matching::start_match(Order::new(4, "1", "0.5", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+
+	let order = market.cancel_order(1).unwrap();
+	assert_eq!(order.side, OrderSide::Bid);
+	assert_eq!(order.filled, Decimal::new(5, 1));
+	assert_eq!(market.bid_book.limit_orders.len(), 0);
+
+	assert_eq!(market.cancel_order(2).unwrap().kind, OrderKind::Market);
+	assert_eq!(market.ask_book.market_orders.len(), 0);
+	assert_eq!(market.cancel_order(3).unwrap().kind, OrderKind::Stop);
+	assert_eq!(market.ask_triggers.len(), 0);
+
+	assert_eq!(market.cancel_order(1).unwrap_err(), MatchingError::UnknownOrder(1));
+	assert_eq!(market.cancel_order(4).unwrap_err(), MatchingError::UnknownOrder(4));
+	assert_eq!(market.cancel_order(5).unwrap_err(), MatchingError::UnknownOrder(5));
+	assert!(market.order_index.is_empty());
+}
+
+#[test]
+fn test_filled_orders_leave_index() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(2, "1", "1", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+	matching::start_match(Order::new(3, "1", "2", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+
+	assert!(market.order_index.is_empty());
+	assert_eq!(market.cancel_order(1).unwrap_err(), MatchingError::UnknownOrder(1));
+	assert_eq!(market.cancel_order(2).unwrap_err(), MatchingError::UnknownOrder(2));
+}
+
+#[test]
+fn test_add_order_errors() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+
+	let order = Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	assert_eq!(market.add_order(order), Err(MatchingError::DuplicateOrder(1)));
+	let order = Order::new(2, "0", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	assert_eq!(market.add_order(order), Err(MatchingError::InvalidPrice(2)));
+	assert_eq!(market.ask_book.limit_orders.len(), 0);
+
+	assert_eq!(market.amend_order(1, Some(Decimal::new(0, 0)), None).unwrap_err(), MatchingError::InvalidPrice(1));
+	assert_eq!(market.amend_order(1, None, Some(Decimal::new(-1, 0))).unwrap_err(), MatchingError::InvalidVolume(1));
+	assert_eq!(market.bid_book.limit_orders.peek().unwrap().volume, Decimal::new(1, 0));
+}
+
+#[test]
+fn test_reused_ids() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	matching::start_match(Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap(), &mut market).unwrap();
+	market.add_order(Order::new(3, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.cancel_order(3).unwrap();
+
+	for &id in [1, 2, 3].iter() {
+		assert_eq!(market.add_order(Order::new(id, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()), Ok(()));
+	}
+	assert_eq!(market.bid_book.limit_orders.len(), 3);
+}
+
+#[test]
+fn test_depth() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(2, "4", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(3, "4", "2", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(4, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+
+	let depth = market.depth(1);
+	assert_eq!(depth.asks, vec![PriceLevel { price: Decimal::new(4, 0), volume: Decimal::new(3, 0), count: 2 }]);
+	assert_eq!(depth.bids, vec![PriceLevel { price: Decimal::new(3, 0), volume: Decimal::new(1, 0), count: 1 }]);
+	assert_eq!(market.depth(5).asks.len(), 2);
+}
+
+#[test]
+fn test_snapshot_replay() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(2, "4", "2", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(3, "4", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(4, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	market.add_order(Order::new(5, "0", "1", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+	let mut iceberg = Order::new(6, "5", "3", OrderKind::Limit, OrderSide::Ask).unwrap();
+	iceberg.display_volume = Some(Decimal::new(1, 0));
+	market.add_order(iceberg).unwrap();
+	matching::start_match(Order::new(7, "4", "0.5", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+
+	let snapshot = market.snapshot();
+	let ids: Vec<u64> = snapshot.asks.limit_orders.iter().map(|entry| entry.id).collect();
+	assert_eq!(ids, vec![2, 3, 1, 6]);
+	assert_eq!(snapshot.asks.limit_orders[0].remaining, Decimal::new(15, 1));
+	assert_eq!(snapshot.bids.market_orders[0].id, 5);
+
+	let json = serde_json::to_string(&snapshot).unwrap();
+	let decoded: MarketSnapshot = serde_json::from_str(&json).unwrap();
+	assert_eq!(decoded, snapshot);
+
+	let mut restored: Market = Market::from_snapshot(&decoded).unwrap();
+	assert_eq!(restored.snapshot(), snapshot);
+	for &(id, price, volume, side) in [(8, "6", "5", OrderSide::Bid), (9, "3", "2", OrderSide::Ask), (10, "5", "1", OrderSide::Ask)].iter() {
+		let expected = matching::start_match(Order::new(id, price, volume, OrderKind::Limit, side).unwrap(), &mut market).unwrap();
+		let actual = matching::start_match(Order::new(id, price, volume, OrderKind::Limit, side).unwrap(), &mut restored).unwrap();
+		let fills = |result: &MatchResult| -> Vec<(u64, Decimal, Decimal)> {
+			result.trades.iter().map(|trade| (trade.maker_id, trade.price, trade.volume)).collect()
+		};
+		assert_eq!(fills(&actual), fills(&expected));
+	}
+	assert_eq!(restored.snapshot(), market.snapshot());
+}
+
+#[test]
+fn test_level_updates() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	assert!(market.take_level_updates().is_empty());
+
+	let mut market = Market::new(1);
+	market.level_updates = Some(Vec::new());
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(2, "5", "2", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(3, "6", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(4, "0", "1", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+	let updates = market.take_level_updates();
+	assert_eq!(updates.iter().map(|update| (update.sequence, update.volume)).collect::<Vec<_>>(),
+		vec![(1, Decimal::new(1, 0)), (2, Decimal::new(3, 0)), (3, Decimal::new(1, 0))]);
+
+	matching::start_match(Order::new(5, "6", "3.5", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	assert_eq!(market.take_level_updates(), vec![
+		LevelUpdate { sequence: 4, side: OrderSide::Ask, price: Decimal::new(5, 0), volume: Decimal::new(0, 0) },
+		LevelUpdate { sequence: 5, side: OrderSide::Ask, price: Decimal::new(6, 0), volume: Decimal::new(5, 1) }
+	]);
+
+	market.amend_order(3, None, Some(Decimal::new(8, 1))).unwrap();
+	market.cancel_order(3).unwrap();
+	assert_eq!(market.take_level_updates(), vec![
+		LevelUpdate { sequence: 6, side: OrderSide::Ask, price: Decimal::new(6, 0), volume: Decimal::new(3, 1) },
+		LevelUpdate { sequence: 7, side: OrderSide::Ask, price: Decimal::new(6, 0), volume: Decimal::new(0, 0) }
+	]);
+	assert!(market.depth(10).asks.is_empty());
+}
+
+#[test]
+fn test_level_resync() {
+	let mut market = Market::new(1);
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(2, "6", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(3, "4", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	let depth = market.depth(usize::MAX);
+	assert_eq!((depth.level_sequence, depth.asks.len(), depth.bids.len()), (3, 2, 1));
+
+	let mut restored: Market = Market::from_snapshot(&market.snapshot()).unwrap();
+	assert_eq!(restored.depth(1).level_sequence, 3);
+	restored.level_updates = Some(Vec::new());
+	restored.cancel_order(2).unwrap();
+	assert_eq!(restored.take_level_updates()[0].sequence, 4);
+}
+
+#[test]
+fn test_iceberg_levels() {
+	let mut market = Market::new(1);
+	market.level_updates = Some(Vec::new());
+	let mut order = Order::new(1, "5", "100", OrderKind::Limit, OrderSide::Ask).unwrap();
+	order.display_volume = Some(Decimal::new(1, 0));
+	market.add_order(order).unwrap();
+	assert_eq!(market.depth(1).asks, vec![PriceLevel { price: Decimal::new(5, 0), volume: Decimal::new(1, 0), count: 1 }]);
+	assert_eq!(market.take_level_updates()[0].volume, Decimal::new(1, 0));
+
+	// the first slice is taken whole and refilled, the second only in part
+	matching::start_match(Order::new(2, "5", "1.5", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	assert_eq!(market.depth(1).asks, vec![PriceLevel { price: Decimal::new(5, 0), volume: Decimal::new(5, 1), count: 1 }]);
+	assert_eq!(market.take_level_updates().last().unwrap().volume, Decimal::new(5, 1));
+
+	market.amend_order(1, None, Some(Decimal::new(18, 1))).unwrap();
+	assert_eq!(market.depth(1).asks[0].volume, Decimal::new(3, 1));
+	market.cancel_order(1).unwrap();
+	assert!(market.depth(1).asks.is_empty());
+	assert_eq!(market.take_level_updates().last().unwrap().volume, Decimal::new(0, 0));
+}
+
+#[test]
+fn test_bbo() {
+	let mut market = Market::new(1);
+	market.bbo_changes = Some(Vec::new());
+	assert_eq!(market.bbo.spread(), None);
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(2, "6", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(3, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	assert_eq!(market.bbo, Bbo { bid: Some(Decimal::new(2, 0)), ask: Some(Decimal::new(5, 0)) });
+	assert_eq!(market.bbo.spread(), Some(Decimal::new(3, 0)));
+	assert_eq!(market.bbo.mid(), Some(Decimal::new(35, 1)));
+	assert_eq!(market.take_bbo_changes().len(), 2);
+
+	matching::start_match(Order::new(4, "5", "0.5", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	assert!(market.take_bbo_changes().is_empty());
+
+	matching::start_match(Order::new(5, "5", "1", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+	assert_eq!(market.take_bbo_changes(), vec![Bbo { bid: Some(Decimal::new(5, 0)), ask: Some(Decimal::new(6, 0)) }]);
+
+	market.cancel_order(5).unwrap();
+	market.cancel_order(2).unwrap();
+	assert_eq!(market.bbo, Bbo { bid: Some(Decimal::new(2, 0)), ask: None });
+	assert_eq!(market.take_bbo_changes().len(), 2);
+}
+
+#[test]
+fn test_market_json() {
+	let mut market = Market::new(3);
+	market.reference_price = Some(Decimal::new(2, 0));
+	market.add_order(Order::new(1, "5", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	market.add_order(Order::new(2, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	matching::start_match(Order::new(3, "5", "0.5", OrderKind::Limit, OrderSide::Bid).unwrap(), &mut market).unwrap();
+
+	let json = serde_json::to_string(&market).unwrap();
+	let decoded: Market = serde_json::from_str(&json).unwrap();
+	assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+	assert_eq!(decoded.reference_price, Some(Decimal::new(2, 0)));
+	assert_eq!(decoded.last_price, Some(Decimal::new(5, 0)));
+	assert_eq!(decoded.bbo, market.bbo);
+}
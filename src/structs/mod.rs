@@ -1,11 +1,53 @@
 
+mod error;
+pub use self::error::MatchingError;
+
 mod order;
 pub use self::order::Order;
 pub use self::order::OrderKind;
 pub use self::order::OrderSide;
+pub use self::order::TimeInForce;
 
 mod order_book;
 pub use self::order_book::OrderBook;
+pub use self::order_book::PriceLevel;
+pub use self::order_book::LimitOrders;
 
 mod market;
+pub use self::market::Bbo;
+pub use self::market::Depth;
+pub use self::market::LevelUpdate;
 pub use self::market::Market;
+pub use self::market::MarketConfig;
+pub use self::market::MarketOrderRemainder;
+pub use self::market::SelfTradePrevention;
+
+mod trade;
+pub use self::trade::Trade;
+
+mod trigger_book;
+pub use self::trigger_book::TriggerBook;
+
+mod snapshot;
+pub use self::snapshot::BookSnapshot;
+pub use self::snapshot::MarketSnapshot;
+pub use self::snapshot::MarketState;
+pub use self::snapshot::OrderEntry;
+
+mod command;
+pub use self::command::Command;
+pub use self::command::OrderId;
+pub use self::command::Request;
+
+mod candle;
+pub use self::candle::Candle;
+pub use self::candle::CandleAggregator;
+pub use self::candle::Clock;
+pub use self::candle::Interval;
+pub use self::candle::ManualClock;
+pub use self::candle::SystemClock;
+
+mod listener;
+pub use self::listener::Event;
+pub use self::listener::MatchingListener;
+pub use self::listener::RecordingListener;
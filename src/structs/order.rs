@@ -1,40 +1,121 @@
+use std::cmp;
 use std::cmp::Ordering;
 use rust_decimal::Decimal;
 use std::str::FromStr;
-use ds::{WithId};
+use ds::{WithId, WithLevel};
+use super::MatchingError;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OrderKind {
+	#[default]
 	Limit,
-	Market
+	Market,
+	/// Becomes a market order once the last trade price reaches `Order::trigger_price`.
+	Stop,
+	/// Becomes a limit order once the last trade price reaches `Order::trigger_price`.
+	StopLimit
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OrderSide {
 	Ask,
 	Bid
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeInForce {
+	#[default]
+	GoodTillCancel,
+	ImmediateOrCancel,
+	FillOrKill
+}
+
+/// On the wire the side is called `type`, as in the order logs. Everything but the id, price,
+/// volume and side may be left out of the JSON and takes the value `Order::new` would give it.
+/// `filled`, `displayed` and `sequence` are kept by the engine and reset when a new order comes in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
 	pub id: u64,
+	/// Limit price. Market and stop orders may leave it at zero to trade at any price.
 	pub price: Decimal,
 	pub volume: Decimal,
+	#[serde(default = "zero")]
 	pub filled: Decimal,
+	#[serde(default)]
 	pub kind: OrderKind,
-	pub side: OrderSide
+	#[serde(rename = "type")]
+	pub side: OrderSide,
+	#[serde(default)]
+	pub time_in_force: TimeInForce,
+	/// Account the order belongs to, orders of the same owner are kept from trading with each other.
+	#[serde(default)]
+	pub owner: Option<u64>,
+	/// Never take liquidity, the order is rejected or re-priced if it would cross.
+	#[serde(default)]
+	pub post_only: bool,
+	#[serde(default)]
+	pub trigger_price: Option<Decimal>,
+	/// Iceberg peak size, only this much of the order is shown and matchable at a time.
+	#[serde(default)]
+	pub display_volume: Option<Decimal>,
+	/// Remaining volume of the current iceberg slice.
+	#[serde(default = "zero")]
+	pub displayed: Decimal,
+	/// Arrival sequence assigned by the engine, earlier orders win ties at the same price.
+	#[serde(default)]
+	pub sequence: u64
+}
+
+fn zero() -> Decimal {
+	Decimal::new(0, 0)
 }
 
 impl Order {
-	pub fn new(id: u64, price: &str, volume: &str, kind: OrderKind, side: OrderSide) -> Order {
-		Order {
-			id: id,
-			price: Decimal::from_str(price).unwrap(),
-			volume: Decimal::from_str(volume).unwrap(),
+	pub fn new(id: u64, price: &str, volume: &str, kind: OrderKind, side: OrderSide) -> Result<Order, MatchingError> {
+		Ok(Order {
+			id,
+			price: parse_decimal(price)?,
+			volume: parse_decimal(volume)?,
 			filled: Decimal::new(0, 0),
-			kind: kind,
-			side: side
+			kind,
+			side,
+			time_in_force: TimeInForce::GoodTillCancel,
+			owner: None,
+			post_only: false,
+			trigger_price: None,
+			display_volume: None,
+			displayed: Decimal::new(0, 0),
+			sequence: 0
+		})
+	}
+
+	/// Checks the fields a client controls before the order may enter a market.
+	pub fn validate(&self) -> Result<(), MatchingError> {
+		let zero = Decimal::new(0, 0);
+		if self.volume <= zero || self.filled < zero || self.filled > self.volume {
+			return Err(MatchingError::InvalidVolume(self.id))
+		}
+		if self.display_volume.is_some_and(|display_volume| display_volume <= zero) {
+			return Err(MatchingError::InvalidVolume(self.id))
+		}
+		let price_valid = match self.kind {
+			OrderKind::Limit | OrderKind::StopLimit => self.price > zero,
+			OrderKind::Market | OrderKind::Stop => self.price >= zero
+		};
+		if !price_valid {
+			return Err(MatchingError::InvalidPrice(self.id))
+		}
+		let trigger_valid = match self.kind {
+			OrderKind::Stop | OrderKind::StopLimit => self.trigger_price.is_some_and(|trigger_price| trigger_price > zero),
+			OrderKind::Limit | OrderKind::Market => true
+		};
+		if !trigger_valid {
+			return Err(MatchingError::InvalidTriggerPrice(self.id))
 		}
+		Ok(())
 	}
 
 	pub fn is_fulfilled(&self) -> bool {
@@ -44,29 +125,84 @@ impl Order {
 	pub fn volume_remained(&self) -> Decimal {
 		self.volume - self.filled
 	}
+
+	/// Only limit orders can hide volume, `display_volume` is ignored on other kinds.
+	pub fn is_iceberg(&self) -> bool {
+		self.display_volume.is_some() && self.kind == OrderKind::Limit
+	}
+
+	/// Volume a taker can match against right now, only the current slice for icebergs.
+	pub fn volume_displayed(&self) -> Decimal {
+		if self.is_iceberg() {
+			self.displayed
+		} else {
+			self.volume_remained()
+		}
+	}
+
+	/// Forgets what the engine keeps track of itself, so an order coming from a client starts
+	/// unfilled whatever its JSON said.
+	pub fn reset_progress(&mut self) {
+		self.filled = Decimal::new(0, 0);
+		self.displayed = Decimal::new(0, 0);
+		self.sequence = 0;
+	}
+
+	/// Refills an exhausted iceberg slice from the hidden reserve.
+	pub fn replenish(&mut self) {
+		if self.is_iceberg() && self.displayed == Decimal::new(0, 0) {
+			let display_volume = self.display_volume.unwrap();
+			self.displayed = cmp::min(display_volume, self.volume_remained());
+		}
+	}
+
+	/// Market orders without a protective price, including stops triggered into one.
+	pub fn is_unbounded(&self) -> bool {
+		self.kind == OrderKind::Market && self.price == Decimal::new(0, 0)
+	}
+
+	/// Buy stops trigger when the price rises to the trigger price, sell stops when it falls to it.
+	pub fn is_triggered(&self, last_price: Decimal) -> bool {
+		match (self.trigger_price, self.side) {
+			(None, _) => true,
+			(Some(trigger_price), OrderSide::Bid) => last_price >= trigger_price,
+			(Some(trigger_price), OrderSide::Ask) => last_price <= trigger_price
+		}
+	}
+
+	/// Turns a triggered stop order into the order it stands for.
+	pub fn activate(&mut self) {
+		self.kind = match self.kind {
+			OrderKind::Stop => OrderKind::Market,
+			OrderKind::StopLimit => OrderKind::Limit,
+			kind => kind
+		};
+	}
+}
+
+pub fn parse_decimal(value: &str) -> Result<Decimal, MatchingError> {
+	Decimal::from_str(value).map_err(|_| MatchingError::InvalidDecimal(value.to_string()))
 }
 
 impl Ord for Order {
 	fn cmp(&self, other: &Order) -> Ordering {
-		match self.side {
+		let by_price = match self.side {
 			OrderSide::Ask => other.price.cmp(&self.price),
 			OrderSide::Bid => self.price.cmp(&other.price)
-		}
+		};
+		by_price.then_with(|| other.sequence.cmp(&self.sequence))
 	}
 }
 
 impl PartialOrd for Order {
 	fn partial_cmp(&self, other: &Order) -> Option<Ordering> {
-		match self.side {
-			OrderSide::Ask => other.price.partial_cmp(&self.price),
-			OrderSide::Bid => self.price.partial_cmp(&other.price)
-		}
+		Some(self.cmp(other))
 	}
 }
 
 impl PartialEq for Order {
 	fn eq(&self, other: &Order) -> bool {
-		self.price == other.price
+		self.price == other.price && self.sequence == other.sequence
 	}
 }
 
@@ -78,18 +214,129 @@ impl WithId for Order {
 	}
 }
 
+/// Asks are leveled by their negated price, so the best price is the greatest level on either side.
+impl WithLevel for Order {
+	type Level = Decimal;
+
+	fn level(&self) -> Decimal {
+		match self.side {
+			OrderSide::Ask => -self.price,
+			OrderSide::Bid => self.price
+		}
+	}
+}
+
 #[test]
 fn lower_price_should_be_greater_for_ask_order() {
-	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Ask);
-	let order_b = Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Ask);
+	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	let order_b = Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
 
 	assert!(order_a > order_b);
 }
 
 #[test]
 fn higher_price_should_be_greater_for_bid_order() {
-	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_b = Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Bid);
+	let order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_b = Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
 
 	assert!(order_a < order_b);
 }
+
+#[test]
+fn earlier_order_should_be_greater_at_same_price() {
+	let mut order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	let mut order_b = Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	order_a.sequence = 1;
+	order_b.sequence = 2;
+	assert!(order_a > order_b);
+
+	let mut order_a = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let mut order_b = Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	order_a.sequence = 1;
+	order_b.sequence = 2;
+	assert!(order_a > order_b);
+
+	order_b.price = Decimal::new(2, 0);
+	assert!(order_a < order_b);
+}
+
+#[test]
+fn test_stop_order_triggered() {
+	let mut order = Order::new(1, "5", "1", OrderKind::StopLimit, OrderSide::Bid).unwrap();
+	order.trigger_price = Some(Decimal::new(3, 0));
+	assert!(!order.is_triggered(Decimal::new(2, 0)));
+	assert!(order.is_triggered(Decimal::new(3, 0)));
+	assert!(order.is_triggered(Decimal::new(4, 0)));
+
+	let mut order = Order::new(1, "1", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+	order.trigger_price = Some(Decimal::new(3, 0));
+	assert!(order.is_triggered(Decimal::new(2, 0)));
+	assert!(order.is_triggered(Decimal::new(3, 0)));
+	assert!(!order.is_triggered(Decimal::new(4, 0)));
+
+	order.activate();
+	assert_eq!(order.kind, OrderKind::Market);
+}
+
+#[test]
+fn test_iceberg_slices() {
+	let mut order = Order::new(1, "1", "5", OrderKind::Limit, OrderSide::Bid).unwrap();
+	assert_eq!(order.volume_displayed(), Decimal::new(5, 0));
+
+	order.display_volume = Some(Decimal::new(2, 0));
+	order.replenish();
+	assert_eq!(order.volume_displayed(), Decimal::new(2, 0));
+
+	order.filled = Decimal::new(4, 0);
+	order.displayed = Decimal::new(0, 0);
+	order.replenish();
+	assert_eq!(order.volume_displayed(), Decimal::new(1, 0));
+}
+
+#[test]
+fn test_invalid_decimal() {
+	let result = Order::new(1, "1.x", "1", OrderKind::Limit, OrderSide::Bid);
+	assert_eq!(result.unwrap_err(), MatchingError::InvalidDecimal("1.x".to_string()));
+	let result = Order::new(1, "1", "", OrderKind::Limit, OrderSide::Bid);
+	assert_eq!(result.unwrap_err(), MatchingError::InvalidDecimal("".to_string()));
+}
+
+#[test]
+fn test_validate() {
+	assert!(Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap().validate().is_ok());
+	assert!(Order::new(1, "0", "1", OrderKind::Market, OrderSide::Bid).unwrap().validate().is_ok());
+
+	let order = Order::new(1, "1", "0", OrderKind::Limit, OrderSide::Bid).unwrap();
+	assert_eq!(order.validate(), Err(MatchingError::InvalidVolume(1)));
+	let order = Order::new(2, "0", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	assert_eq!(order.validate(), Err(MatchingError::InvalidPrice(2)));
+	let order = Order::new(3, "-1", "1", OrderKind::Market, OrderSide::Bid).unwrap();
+	assert_eq!(order.validate(), Err(MatchingError::InvalidPrice(3)));
+
+	let mut order = Order::new(4, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	order.display_volume = Some(Decimal::new(0, 0));
+	assert_eq!(order.validate(), Err(MatchingError::InvalidVolume(4)));
+
+	let mut order = Order::new(5, "1", "1", OrderKind::StopLimit, OrderSide::Bid).unwrap();
+	assert_eq!(order.validate(), Err(MatchingError::InvalidTriggerPrice(5)));
+	order.trigger_price = Some(Decimal::new(1, 0));
+	assert!(order.validate().is_ok());
+}
+
+#[test]
+fn test_order_json() {
+	let order: Order = serde_json::from_str(r#"{"id":1,"price":"0.5","volume":"2","type":"ask","created_at":0}"#).unwrap();
+	assert_eq!((order.id, order.price, order.volume), (1, Decimal::new(5, 1), Decimal::new(2, 0)));
+	assert_eq!((order.side, order.kind, order.time_in_force), (OrderSide::Ask, OrderKind::Limit, TimeInForce::GoodTillCancel));
+	assert_eq!(order.filled, Decimal::new(0, 0));
+
+	let mut order = Order::new(2, "3", "1.25", OrderKind::StopLimit, OrderSide::Bid).unwrap();
+	order.trigger_price = Some(Decimal::new(4, 0));
+	let json = serde_json::to_value(&order).unwrap();
+	assert_eq!(json["price"], "3");
+	assert_eq!(json["volume"], "1.25");
+	assert_eq!(json["type"], "bid");
+	assert_eq!(json["kind"], "stop_limit");
+	let decoded: Order = serde_json::from_value(json).unwrap();
+	assert_eq!((decoded.id, decoded.volume, decoded.trigger_price), (2, order.volume, order.trigger_price));
+}
@@ -1,77 +1,258 @@
-use std::collections::LinkedList;
-use std::cmp::Ord;
 use std::cmp::Ordering;
-use std::cmp;
+use std::collections::BTreeMap;
 use rust_decimal::Decimal;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error;
 use super::*;
-use ds::{Heap, List};
+use ds::{Queue, List};
+#[cfg(not(feature = "tree"))]
+use ds::Heap;
+#[cfg(feature = "tree")]
+use ds::Tree;
 
+/// Container of the resting limit orders, the binary heap unless built with the `tree` feature.
+#[cfg(not(feature = "tree"))]
+pub type LimitOrders = Heap<Order>;
+#[cfg(feature = "tree")]
+pub type LimitOrders = Tree<Order>;
+
+/// Resting limit orders at one price, aggregated. Only the displayed slice of icebergs counts.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PriceLevel {
+	pub price: Decimal,
+	pub volume: Decimal,
+	pub count: usize
+}
+
+/// Resting orders of one side, limit orders in `L` and market orders in `M`.
 #[derive(Debug)]
-pub struct OrderBook {
+pub struct OrderBook<L = LimitOrders, M = List<Order>> {
 	pub side: OrderSide,
 	pub price_requirement: Ordering,
-	pub limit_orders: Heap<Order>,
-	pub market_orders: List<Order>
+	pub limit_orders: L,
+	pub market_orders: M,
+	/// Aggregated limit orders by price, kept in step with `limit_orders`.
+	pub levels: BTreeMap<Decimal, PriceLevel>,
+	/// Prices whose level changed since `take_changed_levels` was last called.
+	pub changed_levels: Vec<Decimal>
 }
 
-impl OrderBook where {
+impl OrderBook {
 	pub fn new(side: OrderSide) -> OrderBook {
+		OrderBook::with_containers(side)
+	}
+}
+
+impl<L: Queue<Order>, M: Queue<Order>> OrderBook<L, M> {
+	pub fn with_containers(side: OrderSide) -> OrderBook<L, M> {
 		let price_requirement = match side {
 			OrderSide::Ask => Ordering::Greater,
 			OrderSide::Bid => Ordering::Less
 		};
 		OrderBook {
-			side: side,
-			price_requirement: price_requirement,
-			limit_orders: Heap::new(),
-			market_orders: List::new()
+			side,
+			price_requirement,
+			limit_orders: L::default(),
+			market_orders: M::default(),
+			levels: BTreeMap::new(),
+			changed_levels: Vec::new()
 		}
 	}
 
-	pub fn add_order(&mut self, order: Order) {
-		assert!(order.side == self.side);
+	pub fn add_order(&mut self, mut order: Order) -> Result<(), MatchingError> {
+		if order.side != self.side {
+			return Err(MatchingError::SideMismatch(order.id))
+		}
+		order.replenish();
 		match order.kind {
-			OrderKind::Limit => self.limit_orders.push(order),
-			OrderKind::Market => self.market_orders.push_back(order)
+			OrderKind::Limit => {
+				self.insert_level(&order);
+				self.limit_orders.push(order)
+			},
+			OrderKind::Market => self.market_orders.push(order),
+			OrderKind::Stop | OrderKind::StopLimit => return Err(MatchingError::KindMismatch(order.id))
 		}
+		Ok(())
 	}
 
-	pub fn cancel_order(&mut self, order: Order) {
-		assert!(order.side == self.side);
-		match order.kind {
-			OrderKind::Limit => self.limit_orders.remove(order.id),
-			OrderKind::Market => self.market_orders.remove(order.id)
+	pub fn cancel_order(&mut self, kind: OrderKind, id: u64) -> Option<Order> {
+		match kind {
+			OrderKind::Limit => {
+				let order = self.limit_orders.remove(id)?;
+				self.remove_level(&order);
+				Some(order)
+			},
+			OrderKind::Market => self.market_orders.remove(id),
+			OrderKind::Stop | OrderKind::StopLimit => None
+		}
+	}
+
+	/// Removes the best limit order.
+	pub fn pop_limit_order(&mut self) -> Option<Order> {
+		let order = self.limit_orders.pop()?;
+		self.remove_level(&order);
+		Some(order)
+	}
+
+	/// Takes `volume` off the level at `price` after the displayed volume of a resting limit order
+	/// there was filled or reduced.
+	pub fn reduce_level(&mut self, price: Decimal, volume: Decimal) {
+		if let Some(level) = self.levels.get_mut(&price) {
+			level.volume -= volume;
+			self.changed_levels.push(price);
 		}
 	}
 
+	/// Prices of the levels changed since the last call, each once.
+	pub fn take_changed_levels(&mut self) -> Vec<Decimal> {
+		let mut prices: Vec<Decimal> = self.changed_levels.drain(..).collect();
+		prices.sort();
+		prices.dedup();
+		prices
+	}
+
+	fn insert_level(&mut self, order: &Order) {
+		let level = self.levels.entry(order.price)
+			.or_insert(PriceLevel { price: order.price, volume: Decimal::new(0, 0), count: 0 });
+		level.volume += order.volume_displayed();
+		level.count += 1;
+		self.changed_levels.push(order.price);
+	}
+
+	fn remove_level(&mut self, order: &Order) {
+		let empty = match self.levels.get_mut(&order.price) {
+			Some(level) => {
+				level.volume -= order.volume_displayed();
+				level.count -= 1;
+				level.count == 0
+			},
+			None => return
+		};
+		if empty {
+			self.levels.remove(&order.price);
+		}
+		self.changed_levels.push(order.price);
+	}
+
+	/// Changing the volume of a limit order through this has to be followed by `reduce_level`.
+	pub fn get_order_mut(&mut self, kind: OrderKind, id: u64) -> Option<&mut Order> {
+		match kind {
+			OrderKind::Limit => self.limit_orders.get_mut(id),
+			OrderKind::Market => self.market_orders.get_mut(id),
+			OrderKind::Stop | OrderKind::StopLimit => None
+		}
+	}
+
+	/// Limit orders in matching priority, best first.
+	pub fn sorted_limit_orders(&self) -> Vec<&Order> {
+		self.limit_orders.ordered().collect()
+	}
+
+	/// Resting orders in matching priority, limit orders best first and market orders in arrival order.
+	pub fn snapshot(&self) -> BookSnapshot {
+		BookSnapshot {
+			limit_orders: self.sorted_limit_orders().into_iter().map(OrderEntry::new).collect(),
+			market_orders: self.market_orders.ordered().map(OrderEntry::new).collect()
+		}
+	}
+
+	/// Hands `orders` to `push` in arrival order. Orders at one price have to go back that way
+	/// to keep their time priority, whatever container wrote them out.
+	pub fn restore<E, F: FnMut(Order) -> Result<(), E>>(mut orders: Vec<Order>, push: F) -> Result<(), E> {
+		orders.sort_by_key(|order| order.sequence);
+		orders.into_iter().try_for_each(push)
+	}
+
+	/// The best `n` price levels of the limit orders, best first.
+	pub fn depth(&self, n: usize) -> Vec<PriceLevel> {
+		match self.side {
+			OrderSide::Ask => self.levels.values().take(n).cloned().collect(),
+			OrderSide::Bid => self.levels.values().rev().take(n).cloned().collect()
+		}
+	}
+}
+
+#[derive(Serialize)]
+struct BookRef<'a> {
+	side: OrderSide,
+	limit_orders: Vec<&'a Order>,
+	market_orders: Vec<&'a Order>
+}
+
+#[derive(Deserialize)]
+struct BookOwned {
+	side: OrderSide,
+	limit_orders: Vec<Order>,
+	market_orders: Vec<Order>
+}
+
+/// Only the side and the orders are written, everything else is derived from them on the way back.
+impl<L: Queue<Order>, M: Queue<Order>> Serialize for OrderBook<L, M> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		BookRef {
+			side: self.side,
+			limit_orders: self.limit_orders.ordered().collect(),
+			market_orders: self.market_orders.ordered().collect()
+		}.serialize(serializer)
+	}
+}
+
+impl<'de, L: Queue<Order>, M: Queue<Order>> Deserialize<'de> for OrderBook<L, M> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OrderBook<L, M>, D::Error> {
+		let owned = BookOwned::deserialize(deserializer)?;
+		let mut book = OrderBook::with_containers(owned.side);
+		let orders = owned.limit_orders.into_iter().chain(owned.market_orders).collect();
+		OrderBook::<L, M>::restore(orders, |order| book.add_order(order)).map_err(D::Error::custom)?;
+		book.changed_levels.clear();
+		Ok(book)
+	}
+}
+
+#[test]
+fn test_depth() {
+	let mut book = OrderBook::new(OrderSide::Bid);
+	book.add_order(Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	book.add_order(Order::new(2, "3", "1.5", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	book.add_order(Order::new(3, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	book.add_order(Order::new(4, "2", "2", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	book.add_order(Order::new(5, "0", "2", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+
+	let levels = book.depth(2);
+	assert_eq!(levels, vec![
+		PriceLevel { price: Decimal::new(3, 0), volume: Decimal::new(15, 1), count: 1 },
+		PriceLevel { price: Decimal::new(2, 0), volume: Decimal::new(3, 0), count: 2 }
+	]);
+	assert_eq!(book.depth(10).len(), 3);
+	assert_eq!(book.depth(0).len(), 0);
+	assert_eq!(book.limit_orders.len(), 4);
 }
 
 #[test]
 fn test_best_limit_order() {
-	let order_a = Order::new(1, "3", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_b = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_c = Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Bid);
-	let order_d = Order::new(1, "3", "1", OrderKind::Limit, OrderSide::Bid);
+	let order_a = Order::new(1, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_b = Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_c = Order::new(3, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let order_d = Order::new(4, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
 
 	let mut book = OrderBook::new(OrderSide::Bid);
-	book.add_order(order_a);
-	book.add_order(order_b);
-	book.add_order(order_c);
-	book.add_order(order_d);
+	book.add_order(order_a).unwrap();
+	book.add_order(order_b).unwrap();
+	book.add_order(order_c).unwrap();
+	book.add_order(order_d).unwrap();
 
 	assert!(book.limit_orders.len() == 4);
 	assert!(book.limit_orders.peek().unwrap().price == Decimal::new(3, 0));
 
-	let order_a = Order::new(1, "3", "1", OrderKind::Limit, OrderSide::Ask);
-	let order_b = Order::new(1, "1", "1", OrderKind::Limit, OrderSide::Ask);
-	let order_c = Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Ask);
-	let order_d = Order::new(1, "3", "1", OrderKind::Limit, OrderSide::Ask);
+	let order_a = Order::new(1, "3", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	let order_b = Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	let order_c = Order::new(3, "2", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+	let order_d = Order::new(4, "3", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
 
 	let mut book = OrderBook::new(OrderSide::Ask);
-	book.add_order(order_a);
-	book.add_order(order_b);
-	book.add_order(order_c);
-	book.add_order(order_d);
+	book.add_order(order_a).unwrap();
+	book.add_order(order_b).unwrap();
+	book.add_order(order_c).unwrap();
+	book.add_order(order_d).unwrap();
 
 	assert!(book.limit_orders.len() == 4);
 	assert!(book.limit_orders.peek().unwrap().price == Decimal::new(1, 0));
@@ -79,9 +260,72 @@ fn test_best_limit_order() {
 
 #[test]
 fn test_place_market_order() {
-	let order = Order::new(1, "3", "1", OrderKind::Market, OrderSide::Ask);
+	let order = Order::new(1, "3", "1", OrderKind::Market, OrderSide::Ask).unwrap();
 	let mut book = OrderBook::new(OrderSide::Ask);
 
-	book.add_order(order);
+	book.add_order(order).unwrap();
 	assert!(book.market_orders.len() == 1);
 }
+
+#[test]
+fn test_cancel_unknown_order() {
+	let mut book = OrderBook::new(OrderSide::Ask);
+	book.add_order(Order::new(1, "3", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	book.add_order(Order::new(2, "3", "1", OrderKind::Market, OrderSide::Ask).unwrap()).unwrap();
+
+	assert!(book.cancel_order(OrderKind::Limit, 2).is_none());
+	assert!(book.cancel_order(OrderKind::Market, 1).is_none());
+	assert!(book.cancel_order(OrderKind::Market, 3).is_none());
+	assert_eq!(book.cancel_order(OrderKind::Limit, 1).unwrap().id, 1);
+	assert_eq!(book.cancel_order(OrderKind::Market, 2).unwrap().id, 2);
+}
+
+#[test]
+fn test_add_order_to_wrong_book() {
+	let mut book = OrderBook::new(OrderSide::Ask);
+
+	let order = Order::new(1, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	assert_eq!(book.add_order(order), Err(MatchingError::SideMismatch(1)));
+	let order = Order::new(2, "3", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+	assert_eq!(book.add_order(order), Err(MatchingError::KindMismatch(2)));
+	assert_eq!(book.limit_orders.len(), 0);
+	assert_eq!(book.market_orders.len(), 0);
+}
+
+#[test]
+fn test_levels() {
+	let mut book = OrderBook::new(OrderSide::Ask);
+	book.add_order(Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	book.add_order(Order::new(2, "2", "3", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	book.add_order(Order::new(3, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap()).unwrap();
+	assert_eq!(book.take_changed_levels(), vec![Decimal::new(1, 0), Decimal::new(2, 0)]);
+	assert!(book.take_changed_levels().is_empty());
+
+	book.cancel_order(OrderKind::Limit, 2).unwrap();
+	book.reduce_level(Decimal::new(2, 0), Decimal::new(5, 1));
+	assert_eq!(book.levels[&Decimal::new(2, 0)], PriceLevel { price: Decimal::new(2, 0), volume: Decimal::new(5, 1), count: 1 });
+	assert_eq!(book.take_changed_levels(), vec![Decimal::new(2, 0)]);
+
+	assert_eq!(book.pop_limit_order().unwrap().id, 3);
+	assert!(!book.levels.contains_key(&Decimal::new(1, 0)));
+	assert_eq!(book.take_changed_levels(), vec![Decimal::new(1, 0)]);
+}
+
+#[test]
+fn test_book_json() {
+	let mut book = OrderBook::new(OrderSide::Bid);
+	book.add_order(Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	book.add_order(Order::new(2, "3", "1", OrderKind::Limit, OrderSide::Bid).unwrap()).unwrap();
+	book.add_order(Order::new(3, "0", "1", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+	book.add_order(Order::new(4, "0", "2", OrderKind::Market, OrderSide::Bid).unwrap()).unwrap();
+
+	let json = serde_json::to_string(&book).unwrap();
+	let decoded: OrderBook = serde_json::from_str(&json).unwrap();
+	assert_eq!(decoded.snapshot(), book.snapshot());
+	assert_eq!(decoded.levels, book.levels);
+	assert_eq!(decoded.price_requirement, Ordering::Less);
+	assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+
+	let json = json.replacen("\"type\":\"bid\"", "\"type\":\"ask\"", 1);
+	assert!(serde_json::from_str::<OrderBook>(&json).is_err());
+}
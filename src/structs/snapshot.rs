@@ -0,0 +1,93 @@
+use rust_decimal::Decimal;
+use super::*;
+
+/// A resting order as it stands in its book.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct OrderEntry {
+	pub id: u64,
+	pub price: Decimal,
+	pub remaining: Decimal,
+	pub sequence: u64,
+	pub owner: Option<u64>,
+	pub display_volume: Option<Decimal>,
+	pub displayed: Decimal
+}
+
+impl OrderEntry {
+	pub fn new(order: &Order) -> OrderEntry {
+		OrderEntry {
+			id: order.id,
+			price: order.price,
+			remaining: order.volume_remained(),
+			sequence: order.sequence,
+			owner: order.owner,
+			display_volume: order.display_volume,
+			displayed: order.displayed
+		}
+	}
+
+	/// Rebuilds the resting order, what it filled before the snapshot is dropped.
+	pub fn to_order(&self, kind: OrderKind, side: OrderSide) -> Order {
+		Order {
+			id: self.id,
+			price: self.price,
+			volume: self.remaining,
+			filled: Decimal::new(0, 0),
+			kind,
+			side,
+			time_in_force: TimeInForce::GoodTillCancel,
+			owner: self.owner,
+			post_only: false,
+			trigger_price: None,
+			display_volume: self.display_volume,
+			displayed: self.displayed,
+			sequence: self.sequence
+		}
+	}
+}
+
+/// Resting orders of one book, each list in matching priority.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BookSnapshot {
+	pub limit_orders: Vec<OrderEntry>,
+	pub market_orders: Vec<OrderEntry>
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MarketSnapshot {
+	pub id: u64,
+	/// Last arrival sequence handed out, so a restored market keeps numbering where this one was.
+	pub order_sequence: u64,
+	/// Last level update sequence, so level updates go on numbering where this market was.
+	#[serde(default)]
+	pub level_sequence: u64,
+	pub asks: BookSnapshot,
+	pub bids: BookSnapshot
+}
+
+/// Everything a `Market` needs to carry on exactly where it was, apart from candles and listeners.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketState {
+	pub id: u64,
+	pub last_price: Option<Decimal>,
+	pub trade_sequence: u64,
+	pub order_sequence: u64,
+	pub level_sequence: u64,
+	pub post_only_tick: Option<Decimal>,
+	pub self_trade_prevention: Option<SelfTradePrevention>,
+	pub reference_price: Option<Decimal>,
+	pub market_order_remainder: MarketOrderRemainder,
+	/// Every resting and untriggered order, market orders in their queue order.
+	pub orders: Vec<Order>
+}
+
+#[test]
+fn test_entry_round_trip() {
+	let mut order = Order::new(1, "2", "3", OrderKind::Limit, OrderSide::Ask).unwrap();
+	order.filled = Decimal::new(1, 0);
+	order.sequence = 7;
+	let restored = OrderEntry::new(&order).to_order(OrderKind::Limit, OrderSide::Ask);
+	assert_eq!(restored.id, 1);
+	assert_eq!(restored.volume_remained(), Decimal::new(2, 0));
+	assert_eq!(restored.sequence, 7);
+}
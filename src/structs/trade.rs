@@ -0,0 +1,46 @@
+use rust_decimal::Decimal;
+use super::*;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Trade {
+	pub market_id: u64,
+	pub sequence: u64,
+	pub maker_id: u64,
+	pub taker_id: u64,
+	pub price: Decimal,
+	pub volume: Decimal,
+	pub taker_side: OrderSide,
+	/// Volume each side has left to fill after this trade.
+	pub maker_remaining: Decimal,
+	pub taker_remaining: Decimal
+}
+
+impl Trade {
+	pub fn new(market_id: u64, sequence: u64, taker: &Order, maker: &Order, price: Decimal, volume: Decimal) -> Trade {
+		Trade {
+			market_id,
+			sequence,
+			maker_id: maker.id,
+			taker_id: taker.id,
+			price,
+			volume,
+			taker_side: taker.side,
+			maker_remaining: maker.volume_remained(),
+			taker_remaining: taker.volume_remained()
+		}
+	}
+}
+
+#[test]
+fn test_new_trade() {
+	let taker = Order::new(1, "2", "1", OrderKind::Limit, OrderSide::Bid).unwrap();
+	let maker = Order::new(2, "1", "1", OrderKind::Limit, OrderSide::Ask).unwrap();
+
+	let trade = Trade::new(7, 1, &taker, &maker, maker.price, Decimal::new(1, 0));
+	assert_eq!(trade.market_id, 7);
+	assert_eq!(trade.maker_id, 2);
+	assert_eq!(trade.taker_id, 1);
+	assert_eq!(trade.price, Decimal::new(1, 0));
+	assert_eq!(trade.taker_side, OrderSide::Bid);
+	assert_eq!(trade.maker_remaining, Decimal::new(1, 0));
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use rust_decimal::Decimal;
+use super::*;
+
+/// Holds stop orders of one side until the last trade price reaches their trigger price.
+/// Orders in here are invisible to matching.
+#[derive(Debug)]
+pub struct TriggerBook {
+	pub side: OrderSide,
+	/// Keyed by trigger price, negated for sell stops, then sequence, so the first key is released first.
+	orders: BTreeMap<(Decimal, u64), Order>,
+	keys: HashMap<u64, (Decimal, u64)>
+}
+
+impl TriggerBook {
+	pub fn new(side: OrderSide) -> TriggerBook {
+		TriggerBook {
+			side,
+			orders: BTreeMap::new(),
+			keys: HashMap::new()
+		}
+	}
+
+	pub fn add_order(&mut self, order: Order) -> Result<(), MatchingError> {
+		if order.side != self.side {
+			return Err(MatchingError::SideMismatch(order.id))
+		}
+		if order.kind != OrderKind::Stop && order.kind != OrderKind::StopLimit {
+			return Err(MatchingError::KindMismatch(order.id))
+		}
+		let trigger_price = order.trigger_price.unwrap_or(Decimal::new(0, 0));
+		let key = match self.side {
+			OrderSide::Bid => (trigger_price, order.sequence),
+			OrderSide::Ask => (-trigger_price, order.sequence)
+		};
+		self.keys.insert(order.id, key);
+		self.orders.insert(key, order);
+		Ok(())
+	}
+
+	/// Orders in the order they would be released.
+	pub fn iter(&self) -> impl Iterator<Item = &Order> {
+		self.orders.values()
+	}
+
+	pub fn cancel_order(&mut self, id: u64) -> Option<Order> {
+		self.keys.remove(&id).and_then(|key| self.orders.remove(&key))
+	}
+
+	/// Changes made through the reference must not affect the trigger price or sequence.
+	pub fn get_mut(&mut self, id: u64) -> Option<&mut Order> {
+		let orders = &mut self.orders;
+		self.keys.get(&id).and_then(move |key| orders.get_mut(key))
+	}
+
+	/// Removes the next order triggered by `last_price`. Buy stops are released from the lowest
+	/// trigger price up, sell stops from the highest down, earlier orders first at the same trigger.
+	pub fn pop_triggered(&mut self, last_price: Decimal) -> Option<Order> {
+		match self.orders.keys().next().cloned() {
+			Some(key) if self.orders[&key].is_triggered(last_price) => {
+				self.keys.remove(&self.orders[&key].id);
+				self.orders.remove(&key)
+			},
+			_ => None
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.orders.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.orders.is_empty()
+	}
+}
+
+#[test]
+fn test_pop_triggered() {
+	let mut book = TriggerBook::new(OrderSide::Bid);
+	for &(id, trigger) in [(1, 3), (2, 1), (3, 2), (4, 1)].iter() {
+		let mut order = Order::new(id, "5", "1", OrderKind::StopLimit, OrderSide::Bid).unwrap();
+		order.trigger_price = Some(Decimal::new(trigger, 0));
+		order.sequence = id;
+		book.add_order(order).unwrap();
+	}
+
+	assert!(book.pop_triggered(Decimal::new(5, 1)).is_none());
+	assert_eq!(book.pop_triggered(Decimal::new(2, 0)).unwrap().id, 2);
+	assert_eq!(book.pop_triggered(Decimal::new(2, 0)).unwrap().id, 4);
+	assert_eq!(book.pop_triggered(Decimal::new(2, 0)).unwrap().id, 3);
+	assert!(book.pop_triggered(Decimal::new(2, 0)).is_none());
+	assert_eq!(book.len(), 1);
+
+	let mut book = TriggerBook::new(OrderSide::Ask);
+	for &(id, trigger) in [(1, 3), (2, 1), (3, 2)].iter() {
+		let mut order = Order::new(id, "1", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+		order.trigger_price = Some(Decimal::new(trigger, 0));
+		order.sequence = id;
+		book.add_order(order).unwrap();
+	}
+
+	assert!(book.pop_triggered(Decimal::new(4, 0)).is_none());
+	assert_eq!(book.pop_triggered(Decimal::new(2, 0)).unwrap().id, 1);
+	assert_eq!(book.pop_triggered(Decimal::new(2, 0)).unwrap().id, 3);
+	assert!(book.pop_triggered(Decimal::new(2, 0)).is_none());
+
+	let mut book = TriggerBook::new(OrderSide::Ask);
+	for &(id, trigger) in [(1, 5), (2, 10), (3, 10), (4, 8)].iter() {
+		let mut order = Order::new(id, "1", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+		order.trigger_price = Some(Decimal::new(trigger, 0));
+		order.sequence = id;
+		book.add_order(order).unwrap();
+	}
+	let ids: Vec<u64> = book.iter().map(|order| order.id).collect();
+	assert_eq!(ids, vec![2, 3, 4, 1]);
+	assert_eq!(book.pop_triggered(Decimal::new(9, 0)).unwrap().id, 2);
+	assert_eq!(book.pop_triggered(Decimal::new(9, 0)).unwrap().id, 3);
+	assert!(book.pop_triggered(Decimal::new(9, 0)).is_none());
+}
+
+#[test]
+fn test_cancel_trigger_order() {
+	let mut book = TriggerBook::new(OrderSide::Ask);
+	let mut order = Order::new(1, "1", "1", OrderKind::Stop, OrderSide::Ask).unwrap();
+	order.trigger_price = Some(Decimal::new(2, 0));
+	book.add_order(order).unwrap();
+
+	assert!(book.cancel_order(2).is_none());
+	assert_eq!(book.cancel_order(1).unwrap().id, 1);
+	assert_eq!(book.len(), 0);
+	assert!(book.pop_triggered(Decimal::new(1, 0)).is_none());
+}
@@ -0,0 +1,199 @@
+use rust_decimal::Decimal;
+use structs::*;
+#[cfg(test)]
+use ds::{Queue, Heap, List, Tree};
+#[cfg(test)]
+use std::collections::HashMap;
+
+/// xorshift64* generator, small and seedable so that generated order flow is the same on every machine.
+#[derive(Debug, Clone)]
+pub struct XorShift {
+	state: u64
+}
+
+impl XorShift {
+	pub fn new(seed: u64) -> XorShift {
+		// the all-zero state would only ever produce zeros
+		XorShift { state: if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed } }
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.state ^= self.state >> 12;
+		self.state ^= self.state << 25;
+		self.state ^= self.state >> 27;
+		self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+	}
+
+	/// Uniform in `[0, 1)`.
+	pub fn next_f64(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+
+	/// Uniform in `[low, high]`.
+	pub fn range(&mut self, low: i64, high: i64) -> i64 {
+		low + (self.next_u64() % (high - low + 1) as u64) as i64
+	}
+
+	pub fn chance(&mut self, probability: f64) -> bool {
+		self.next_f64() < probability
+	}
+}
+
+/// How limit prices are spread, in ticks.
+#[derive(Debug, Clone, Copy)]
+pub enum PriceDistribution {
+	Uniform {
+		low: i64,
+		high: i64
+	},
+	/// Bell shaped around `mean`, so most orders land close to each other and cross often.
+	Normal {
+		mean: i64,
+		deviation: f64
+	}
+}
+
+impl PriceDistribution {
+	pub fn sample(&self, rng: &mut XorShift) -> i64 {
+		let ticks = match *self {
+			PriceDistribution::Uniform { low, high } => rng.range(low, high),
+			PriceDistribution::Normal { mean, deviation } => {
+				// Box-Muller
+				let u = 1.0 - rng.next_f64();
+				let v = rng.next_f64();
+				let z = (-2.0 * u.ln()).sqrt() * (2.0 * ::std::f64::consts::PI * v).cos();
+				mean + (z * deviation).round() as i64
+			}
+		};
+		ticks.max(1)
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct FlowConfig {
+	pub seed: u64,
+	/// Number of commands, submits and cancels together.
+	pub commands: usize,
+	/// Share of commands that cancel a previously submitted order.
+	pub cancel_ratio: f64,
+	/// Share of submitted orders that are market orders.
+	pub market_ratio: f64,
+	pub prices: PriceDistribution,
+	/// Decimal places of a price tick.
+	pub price_scale: u32,
+	/// Volumes are drawn uniformly from one to this many lots of `10^-volume_scale`.
+	pub max_volume: i64,
+	pub volume_scale: u32
+}
+
+impl Default for FlowConfig {
+	fn default() -> FlowConfig {
+		FlowConfig {
+			seed: 1,
+			commands: 100_000,
+			cancel_ratio: 0.3,
+			market_ratio: 0.05,
+			prices: PriceDistribution::Normal { mean: 10_000, deviation: 50.0 },
+			price_scale: 2,
+			max_volume: 1_000,
+			volume_scale: 3
+		}
+	}
+}
+
+/// Generates the command stream described by `config`, the same one for the same config.
+/// Cancels target a random earlier order, which may have been filled by then.
+pub fn generate(config: &FlowConfig) -> Vec<Command> {
+	let mut rng = XorShift::new(config.seed);
+	let mut submitted: Vec<u64> = Vec::new();
+	let mut commands = Vec::with_capacity(config.commands);
+	let mut next_id = 1;
+	while commands.len() < config.commands {
+		if !submitted.is_empty() && rng.chance(config.cancel_ratio) {
+			let index = rng.range(0, submitted.len() as i64 - 1) as usize;
+			commands.push(Command::Cancel(submitted.swap_remove(index)));
+			continue
+		}
+		let side = if rng.chance(0.5) { OrderSide::Ask } else { OrderSide::Bid };
+		let kind = if rng.chance(config.market_ratio) { OrderKind::Market } else { OrderKind::Limit };
+		let price = match kind {
+			OrderKind::Market => Decimal::new(0, 0),
+			_ => Decimal::new(config.prices.sample(&mut rng), config.price_scale)
+		};
+		let volume = Decimal::new(rng.range(1, config.max_volume), config.volume_scale);
+		let mut order = Order::new(next_id, "0", "0", kind, side).unwrap();
+		order.price = price;
+		order.volume = volume;
+		submitted.push(next_id);
+		commands.push(Command::Submit(order));
+		next_id += 1;
+	}
+	commands
+}
+
+#[test]
+fn test_xorshift() {
+	let mut rng = XorShift::new(7);
+	let first: Vec<u64> = (0..4).map(|_| rng.next_u64()).collect();
+	let mut rng = XorShift::new(7);
+	let second: Vec<u64> = (0..4).map(|_| rng.next_u64()).collect();
+	assert_eq!(first, second);
+	assert!((0..1000).all(|_| { let value = rng.range(-2, 2); (-2..=2).contains(&value) }));
+	assert!((0..1000).all(|_| { let value = rng.next_f64(); (0.0..1.0).contains(&value) }));
+}
+
+#[test]
+fn test_generate() {
+	let config = FlowConfig { commands: 10_000, cancel_ratio: 0.25, market_ratio: 0.1, ..FlowConfig::default() };
+	let commands = generate(&config);
+	assert_eq!(commands.len(), 10_000);
+	let json = |commands: &[Command]| serde_json::to_string(commands).unwrap();
+	assert_eq!(json(&commands), json(&generate(&config)));
+	assert_ne!(json(&commands), json(&generate(&FlowConfig { seed: 2, ..config.clone() })));
+
+	let cancels = commands.iter().filter(|command| matches!(**command, Command::Cancel(_))).count();
+	let markets = commands.iter().filter(|command| match **command {
+		Command::Submit(ref order) => order.kind == OrderKind::Market,
+		_ => false
+	}).count();
+	assert!(cancels > 2_000 && cancels < 3_000);
+	assert!(markets > 500 && markets < 1_000);
+
+	// market orders of either side have to find liquidity, or the flow is one-sided
+	let market_sides: HashMap<u64, OrderSide> = commands.iter().filter_map(|command| match *command {
+		Command::Submit(ref order) if order.kind == OrderKind::Market => Some((order.id, order.side)),
+		_ => None
+	}).collect();
+	let mut market = Market::new(1);
+	let mut taken = (0, 0);
+	for command in commands {
+		for trade in market.execute(command).map(|result| result.trades).unwrap_or_default() {
+			match market_sides.get(&trade.taker_id) {
+				Some(&OrderSide::Ask) => taken.0 += 1,
+				Some(&OrderSide::Bid) => taken.1 += 1,
+				None => ()
+			}
+		}
+	}
+	assert!(taken.0 > 100 && taken.1 > 100);
+}
+
+#[cfg(test)]
+fn run<L: Queue<Order>, M: Queue<Order>>(commands: &[Command]) -> (Vec<Trade>, MarketSnapshot) {
+	let mut market: Market<L, M> = Market::with_containers(1);
+	let mut trades = Vec::new();
+	for command in commands.iter().cloned() {
+		if let Ok(result) = market.execute(command) {
+			trades.extend(result.trades);
+		}
+	}
+	(trades, market.snapshot())
+}
+
+#[test]
+fn test_containers_agree() {
+	let commands = generate(&FlowConfig { commands: 20_000, ..FlowConfig::default() });
+	let (trades, snapshot) = run::<Heap<Order>, List<Order>>(&commands);
+	assert!(!trades.is_empty());
+	assert_eq!(run::<Tree<Order>, List<Order>>(&commands), (trades, snapshot));
+}